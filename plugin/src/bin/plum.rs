@@ -0,0 +1,91 @@
+//! The command line interface of the plugin, currently only for formatting diagrams:
+//!
+//! ```text
+//! plum fmt [--indent <n>] [--align-colons] [--sort-members] [--keep-meta-order] [--check] [<file>...]
+//! ```
+//!
+//! Without files, a diagram is read from standard input and written to standard output. Files are
+//! formatted in place, or with `--check`, only reported if they are not formatted.
+
+use std::io::{self, Read};
+use std::process::ExitCode;
+use std::{env, fs};
+
+use parser::diagnostic::Diagnostic;
+use parser::format::{self, Options};
+
+const USAGE: &str =
+    "usage: plum fmt [--indent <n>] [--align-colons] [--sort-members] [--keep-meta-order] [--check] [<file>...]";
+
+fn main() -> ExitCode {
+    match run(env::args().skip(1).collect()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::from(2)
+        },
+    }
+}
+
+/// Runs the command; returns whether all files were formatted, or an error message.
+fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut args = args.into_iter();
+    if args.next().as_deref() != Some("fmt") {
+        return Err(USAGE.to_string());
+    }
+
+    let mut options = Options::default();
+    let mut check = false;
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--indent" => {
+                let indent = args.next().ok_or(USAGE)?;
+                options.indent = indent.parse().map_err(|_| format!("invalid indent: {indent}"))?;
+            },
+            "--align-colons" => options.align_colons = true,
+            "--sort-members" => options.sort_members = true,
+            "--keep-meta-order" => options.normalize_metas = false,
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(true);
+            },
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}\n{USAGE}")),
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map_err(|error| error.to_string())?;
+        let formatted = format_source(&source, &options)?;
+        if check {
+            return Ok(formatted == source);
+        }
+        print!("{formatted}");
+        return Ok(true);
+    }
+
+    let mut all_formatted = true;
+    for file in files {
+        let source = fs::read_to_string(&file).map_err(|error| format!("{file}: {error}"))?;
+        let formatted = format_source(&source, &options).map_err(|error| format!("{file}: {error}"))?;
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{file} is not formatted");
+            all_formatted = false;
+        } else {
+            fs::write(&file, formatted).map_err(|error| format!("{file}: {error}"))?;
+        }
+    }
+    Ok(all_formatted)
+}
+
+fn format_source(source: &str, options: &Options) -> Result<String, String> {
+    parser::parser::parse(source).map_err(|error| Diagnostic::from_parse_error(source, &error).render(source))?;
+    Ok(format::format(source, options))
+}
@@ -0,0 +1,473 @@
+use std::fmt;
+
+use crate::model::helpers::{is_name_continue, is_name_start, KEYWORDS};
+
+/// The kind of a [Token]. Besides the tokens the grammar knows, this includes trivia, i.e.
+/// whitespace and comments, and characters that no token starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    Keyword,
+    /// a name, possibly qualified (`p::A`) or followed by a colon (`role:`)
+    Name,
+    /// an integer, decimal number or angle such as `45deg`
+    Number,
+    String,
+    /// an association mark such as `--`, `<--` or `o--x`
+    Mark,
+    Punctuation,
+    Unknown,
+}
+
+impl TokenKind {
+    /// Whether tokens of this kind are whitespace or comments, which the grammar ignores.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Self::Whitespace | Self::LineComment | Self::BlockComment)
+    }
+}
+
+/// A token together with its byte range in the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Token<'input> {
+    pub kind: TokenKind,
+    pub text: &'input str,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The kind of a [Node].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Diagram,
+    Classifier,
+    Edge,
+    Note,
+    Package,
+    /// a `#[...]` block
+    Metas,
+    /// the part of a classifier or package between braces
+    Body,
+    /// a line of a classifier body: an attribute, operation or enumeration literals
+    Member,
+}
+
+/// A node of the concrete syntax tree. Unlike [crate::model::Diagram], the tree keeps every token,
+/// including comments and blank lines, in source order, so that the source can be reproduced
+/// exactly from it; see the [fmt::Display] implementation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node<'input> {
+    pub kind: NodeKind,
+    pub children: Vec<Child<'input>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Child<'input> {
+    Token(Token<'input>),
+    Node(Node<'input>),
+}
+
+impl<'input> Node<'input> {
+    fn new(kind: NodeKind) -> Self {
+        Self { kind, children: Vec::new() }
+    }
+
+    /// The byte range this node covers in the source, or `None` if it has no tokens.
+    pub fn span(&self) -> Option<(usize, usize)> {
+        let mut tokens = self.tokens();
+        let first = tokens.next()?;
+        let last = tokens.last().unwrap_or(first);
+        Some((first.start, last.end))
+    }
+
+    /// All tokens in this node, in source order.
+    pub fn tokens(&self) -> impl Iterator<Item = &Token<'input>> + '_ {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens.into_iter()
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token<'input>>) {
+        for child in &self.children {
+            match child {
+                Child::Token(token) => tokens.push(token),
+                Child::Node(node) => node.collect_tokens(tokens),
+            }
+        }
+    }
+
+    /// The nodes directly contained in this node, in source order.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node<'input>> + '_ {
+        self.children.iter().filter_map(|child| match child {
+            Child::Node(node) => Some(node),
+            Child::Token(_) => None,
+        })
+    }
+}
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tokens().try_for_each(|token| write!(f, "{}", token.text))
+    }
+}
+
+/// Parses a diagram into a concrete syntax tree. This never fails: the tree follows the structure
+/// of the diagram as far as it is recognizable, and contains all of the source in any case.
+pub fn parse(source: &str) -> Node<'_> {
+    let tokens = tokenize(source);
+    let mut parser = Parser { tokens: &tokens, index: 0 };
+    let mut diagram = Node::new(NodeKind::Diagram);
+    parser.items(&mut diagram);
+    // a stray closing brace ends the item list; keep going after it
+    while parser.peek().is_some() {
+        parser.bump(&mut diagram);
+        parser.items(&mut diagram);
+    }
+    diagram
+}
+
+const PUNCTUATION: &[&str] = &[
+    "#[", "#", "(", ")", "*", "+", ",", "-", "--|>", "->", ".", "..", "..>", "..|>", ".>", "/", ":", ";", "<",
+    "<.", "<..", "<<", "<|--", "<|..", "=", ">", ">>", "[", "]", "{", "}", "~", "«", "»", "▶", "◀",
+];
+
+/// the punctuation of generalizations, realizations and dependencies; see [TokenKind::Mark] for
+/// associations
+const EDGE_PUNCTUATION: &[&str] = &["--|>", "<|--", "..|>", "<|..", "..>", "<..", ".>", "<.", "."];
+
+/// Splits the source into tokens, like the grammar's lexer but keeping trivia. Where several
+/// tokens could start at a position, the longest one is taken, and on a tie, association marks
+/// win over keywords and punctuation, which win over everything else.
+pub fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < source.len() {
+        let rest = &source[start..];
+        let (kind, len) = trivia(rest).unwrap_or_else(|| {
+            let candidates = [
+                (mark_len(rest), TokenKind::Mark),
+                (literal_len(rest, KEYWORDS), TokenKind::Keyword),
+                (literal_len(rest, PUNCTUATION), TokenKind::Punctuation),
+                (name_len(rest), TokenKind::Name),
+                (number_len(rest), TokenKind::Number),
+                (string_len(rest), TokenKind::String),
+            ];
+            // `max_by_key` returns the last maximum, so iterate from lowest to highest priority
+            candidates
+                .into_iter()
+                .rev()
+                .filter_map(|(len, kind)| Some((kind, len?)))
+                .max_by_key(|&(_, len)| len)
+                .unwrap_or((TokenKind::Unknown, rest.chars().next().map_or(1, char::len_utf8)))
+        });
+        let end = start + len;
+        tokens.push(Token { kind, text: &source[start..end], start, end });
+        start = end;
+    }
+    tokens
+}
+
+fn trivia(rest: &str) -> Option<(TokenKind, usize)> {
+    if rest.starts_with("\r\n") {
+        Some((TokenKind::Newline, 2))
+    } else if rest.starts_with(['\n', '\r']) {
+        Some((TokenKind::Newline, 1))
+    } else if rest.starts_with("//") {
+        Some((TokenKind::LineComment, rest.find(['\n', '\r']).unwrap_or(rest.len())))
+    } else if let Some(comment) = rest.strip_prefix("/*") {
+        comment.find("*/").map(|end| (TokenKind::BlockComment, end + 4))
+    } else {
+        let len = rest.find(|c: char| !c.is_whitespace() || c == '\n' || c == '\r').unwrap_or(rest.len());
+        (len > 0).then_some((TokenKind::Whitespace, len))
+    }
+}
+
+fn literal_len(rest: &str, literals: &[&str]) -> Option<usize> {
+    literals.iter().filter(|literal| rest.starts_with(*literal)).map(|literal| literal.len()).max()
+}
+
+/// the length of the association mark regex `([<x]|[o*]?(-x)?)--((x-)?[o*]?|[x>])`
+fn mark_len(rest: &str) -> Option<usize> {
+    const LEFT: &[&str] = &["<", "x", "", "o", "*", "-x", "o-x", "*-x"];
+    const RIGHT: &[&str] = &["", "x-", "o", "*", "x-o", "x-*", "x", ">"];
+    LEFT.iter()
+        .flat_map(|left| RIGHT.iter().map(move |right| format!("{left}--{right}")))
+        .filter(|mark| rest.starts_with(mark.as_str()))
+        .map(|mark| mark.len())
+        .max()
+}
+
+fn simple_name_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices();
+    chars.next().filter(|&(_, c)| is_name_start(c))?;
+    Some(chars.find(|&(_, c)| !is_name_continue(c)).map_or(rest.len(), |(index, _)| index))
+}
+
+/// the length of a name, qualified name, or name followed by a colon
+fn name_len(rest: &str) -> Option<usize> {
+    let mut len = simple_name_len(rest)?;
+    let with_colon = rest[len..].starts_with(':').then_some(len + 1);
+    while let Some(next) = rest[len..].strip_prefix("::").and_then(simple_name_len) {
+        len += 2 + next;
+    }
+    Some(len.max(with_colon.unwrap_or(0)))
+}
+
+fn number_len(rest: &str) -> Option<usize> {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let mut len = usize::from(rest.starts_with(['-', '+']));
+    let integer = digits(&rest[len..]);
+    if integer == 0 {
+        return None;
+    }
+    len += integer;
+    if let Some(fraction) = rest[len..].strip_prefix('.').map(digits).filter(|&fraction| fraction > 0) {
+        len += 1 + fraction;
+    }
+    if let Some(unit) = ["rad", "deg"].iter().find(|unit| rest[len..].starts_with(*unit)) {
+        len += unit.len();
+    }
+    Some(len)
+}
+
+fn string_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    rest.starts_with('"').then_some(())?;
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some(index + 1),
+            '\\' => {
+                chars.next().filter(|&(_, c)| c != '\n' && c != '\r')?;
+            }
+            '\n' | '\r' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+struct Parser<'a, 'input> {
+    tokens: &'a [Token<'input>],
+    index: usize,
+}
+
+impl<'input> Parser<'_, 'input> {
+    fn peek(&self) -> Option<&Token<'input>> {
+        self.tokens.get(self.index)
+    }
+
+    fn bump(&mut self, node: &mut Node<'input>) {
+        if let Some(&token) = self.peek() {
+            node.children.push(Child::Token(token));
+            self.index += 1;
+        }
+    }
+
+    /// the first token that is not trivia or a newline, starting at the current one
+    fn next_significant(&self) -> Option<&Token<'input>> {
+        self.tokens[self.index..].iter().find(|token| !token.kind.is_trivia() && token.kind != TokenKind::Newline)
+    }
+
+    /// Parses items, along with the trivia between them, until the end of the input or a closing
+    /// brace.
+    fn items(&mut self, node: &mut Node<'input>) {
+        while let Some(token) = self.peek() {
+            if token.text == "}" {
+                return;
+            } else if token.kind.is_trivia() || token.kind == TokenKind::Newline {
+                self.bump(node);
+            } else {
+                let item = self.item();
+                node.children.push(Child::Node(item));
+            }
+        }
+    }
+
+    fn item(&mut self) -> Node<'input> {
+        let mut item = Node::new(NodeKind::Classifier);
+        if self.peek().is_some_and(|token| token.text == "#[") {
+            let mut metas = Node::new(NodeKind::Metas);
+            let mut depth = 0usize;
+            while let Some(token) = self.peek() {
+                let text = token.text;
+                if text == "}" {
+                    break;
+                }
+                self.bump(&mut metas);
+                match text {
+                    "#[" | "[" | "(" => depth += 1,
+                    "]" | ")" => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                if depth == 0 {
+                    break;
+                }
+            }
+            item.children.push(Child::Node(metas));
+            // metas may be followed by newlines before the item itself
+            while self.peek().is_some_and(|token| token.kind.is_trivia() || token.kind == TokenKind::Newline) {
+                self.bump(&mut item);
+            }
+        }
+
+        item.kind = match self.next_significant().map(|token| token.text) {
+            Some("note") => NodeKind::Note,
+            Some("package") => NodeKind::Package,
+            _ if self.is_edge() => NodeKind::Edge,
+            _ => NodeKind::Classifier,
+        };
+
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token.text {
+                "(" | "[" => depth += 1,
+                ")" | "]" => depth = depth.saturating_sub(1),
+                "}" if depth == 0 => break,
+                "{" if depth == 0 && matches!(item.kind, NodeKind::Classifier | NodeKind::Package) => {
+                    let body = self.body(item.kind == NodeKind::Package);
+                    item.children.push(Child::Node(body));
+                    continue;
+                }
+                _ if token.kind == TokenKind::Newline && depth == 0 => break,
+                _ => {}
+            }
+            self.bump(&mut item);
+        }
+        item
+    }
+
+    /// whether the current line, outside of brackets, contains an edge's punctuation
+    fn is_edge(&self) -> bool {
+        let mut depth = 0usize;
+        for token in &self.tokens[self.index..] {
+            match token.text {
+                "(" | "[" => depth += 1,
+                ")" | "]" => depth = depth.saturating_sub(1),
+                "{" | "}" => return false,
+                _ if token.kind == TokenKind::Newline && depth == 0 => return false,
+                text if depth == 0 && (token.kind == TokenKind::Mark || EDGE_PUNCTUATION.contains(&text)) => {
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Parses a body in braces: the items of a package, or the members of a classifier.
+    fn body(&mut self, package: bool) -> Node<'input> {
+        let mut body = Node::new(NodeKind::Body);
+        self.bump(&mut body);
+        if package {
+            self.items(&mut body);
+        } else {
+            self.members(&mut body);
+        }
+        if self.peek().is_some_and(|token| token.text == "}") {
+            self.bump(&mut body);
+        }
+        body
+    }
+
+    fn members(&mut self, body: &mut Node<'input>) {
+        while let Some(token) = self.peek() {
+            if token.text == "}" {
+                return;
+            } else if token.kind.is_trivia() || token.kind == TokenKind::Newline || token.text == ";" {
+                self.bump(body);
+            } else {
+                let mut member = Node::new(NodeKind::Member);
+                let mut depth = 0usize;
+                while let Some(token) = self.peek() {
+                    match token.text {
+                        "(" | "[" | "{" => depth += 1,
+                        ")" | "]" => depth = depth.saturating_sub(1),
+                        "}" if depth == 0 => break,
+                        "}" => depth -= 1,
+                        ";" if depth == 0 => break,
+                        _ if token.kind == TokenKind::Newline && depth == 0 => break,
+                        _ => {}
+                    }
+                    self.bump(&mut member);
+                }
+                body.children.push(Child::Node(member));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(source).into_iter().map(|token| (token.kind, token.text)).collect()
+    }
+
+    /// the kinds of the nodes of a tree, nested in parentheses
+    fn structure(node: &Node<'_>) -> String {
+        let children: Vec<_> = node.nodes().map(structure).collect();
+        if children.is_empty() {
+            format!("{:?}", node.kind)
+        } else {
+            format!("{:?}({})", node.kind, children.join(" "))
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+
+        assert_eq!(
+            kinds("class A  // c\r\n/* x */ p::B o--x role: C"),
+            [
+                (Keyword, "class"),
+                (Whitespace, " "),
+                (Name, "A"),
+                (Whitespace, "  "),
+                (LineComment, "// c"),
+                (Newline, "\r\n"),
+                (BlockComment, "/* x */"),
+                (Whitespace, " "),
+                (Name, "p::B"),
+                (Whitespace, " "),
+                (Mark, "o--x"),
+                (Whitespace, " "),
+                (Name, "role:"),
+                (Whitespace, " "),
+                (Name, "C"),
+            ],
+        );
+        assert_eq!(kinds("classes x-- -1.5deg"), [(Name, "classes"), (Whitespace, " "), (Mark, "x--"), (Whitespace, " "), (Number, "-1.5deg")]);
+        assert_eq!(kinds("[0..*]"), [(Punctuation, "["), (Number, "0"), (Punctuation, ".."), (Punctuation, "*"), (Punctuation, "]")]);
+        assert_eq!(kinds("\"a\\\"b\" \"c"), [(String, "\"a\\\"b\""), (Whitespace, " "), (Unknown, "\""), (Name, "c")]);
+        assert_eq!(kinds("/* open"), [(Punctuation, "/"), (Punctuation, "*"), (Whitespace, " "), (Name, "open")]);
+        // like the grammar, names are made of identifier characters, which `²` is not
+        assert_eq!(kinds("café a²"), [(Name, "café"), (Whitespace, " "), (Name, "a"), (Unknown, "²")]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let source = "// the model\n#[pos(0, 0)]\nclass A {\n  - x: Int // x\n  + f()\n}\n\n/* edges */\nA -- B\nnote on A \"n\"\npackage p {\n  enumeration E { X; Y }\n  A ..> p::E\n}\n";
+        let cst = parse(source);
+        assert_eq!(cst.to_string(), source);
+        assert_eq!(cst.span(), Some((0, source.len())));
+        assert_eq!(
+            structure(&cst),
+            "Diagram(Classifier(Metas Body(Member Member)) Edge Note Package(Body(Classifier(Body(Member Member)) Edge)))",
+        );
+
+        let classifier = cst.nodes().next().unwrap();
+        assert_eq!(classifier.span(), Some((13, 61)));
+        assert_eq!(&source[13..61], "#[pos(0, 0)]\nclass A {\n  - x: Int // x\n  + f()\n}");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for source in ["}", "class A {", "#[pos(0", "A -- B }\nclass C", "package p {\n  class A\n} }", "#[\n]\n\n", "«"] {
+            assert_eq!(parse(source).to_string(), source);
+        }
+    }
+}
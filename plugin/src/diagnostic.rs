@@ -0,0 +1,285 @@
+use std::fmt;
+
+use lalrpop_util::ParseError;
+use serde::{Deserialize, Serialize};
+
+use crate::parser;
+
+/// A problem found in a diagram: either a syntax error or a semantic problem found by validation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expected: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message.into())
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message.into())
+    }
+
+    fn new(severity: Severity, message: String) -> Self {
+        Self { severity, message, span: None, expected: Vec::new(), hint: None }
+    }
+
+    /// Locates the diagnostic at a range of byte offsets in the source.
+    pub fn at(mut self, source: &str, (start, end): (usize, usize)) -> Self {
+        self.span = Some(Span::new(source, start, end));
+        self
+    }
+
+    /// Converts a syntax error into a diagnostic, locating it in the source.
+    pub fn from_parse_error(source: &str, error: &parser::Error<'_>) -> Self {
+        let span = |start, end| Some(Span::new(source, start, end));
+        match error {
+            ParseError::InvalidToken { location } => {
+                let mut diagnostic = Self::error("invalid token");
+                diagnostic.span = span(*location, *location + char_len(source, *location));
+                if source.get(*location..).is_some_and(|rest| rest.starts_with('"')) {
+                    diagnostic.hint = Some("strings must be closed on the same line".to_string());
+                }
+                diagnostic
+            }
+            ParseError::UnrecognizedEof { location, expected } => {
+                let mut diagnostic = Self::error("unexpected end of diagram");
+                diagnostic.span = span(*location, *location);
+                diagnostic.expected = describe_tokens(expected);
+                if diagnostic.expects("`}`") {
+                    diagnostic.hint = Some("a `{` may be missing its closing `}`".to_string());
+                }
+                diagnostic
+            }
+            ParseError::UnrecognizedToken { token: (start, token, end), expected } => {
+                let mut diagnostic = Self::error(format!("unexpected {}", describe_input(token.1)));
+                diagnostic.span = span(*start, *end);
+                diagnostic.expected = describe_tokens(expected);
+                if token.1 != "\n" && diagnostic.expects("newline") {
+                    diagnostic.hint = Some("items and members must each start on a new line".to_string());
+                }
+                diagnostic
+            }
+            ParseError::ExtraToken { token: (start, token, end) } => {
+                let mut diagnostic = Self::error(format!("unexpected {}", describe_input(token.1)));
+                diagnostic.span = span(*start, *end);
+                diagnostic
+            }
+            ParseError::User { error } => {
+                let mut diagnostic = Self::error(error.message);
+                diagnostic.span = span(error.start, error.end);
+                diagnostic
+            }
+        }
+    }
+
+    fn expects(&self, token: &str) -> bool {
+        self.expected.iter().any(|expected| expected == token)
+    }
+
+    /// Renders the diagnostic for display, including the affected line of the source with the
+    /// problematic part underlined:
+    ///
+    /// ```text
+    /// error: unexpected `class`
+    ///  --> 1:9
+    ///   |
+    /// 1 | class A class B
+    ///   |         ^^^^^
+    ///   = expected one of: newline, `<`, `as`, `{`
+    ///   = hint: items and members must each start on a new line
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut result = self.to_string();
+        if let Some(span) = &self.span {
+            let line = source.lines().nth(span.start.line - 1).unwrap_or("");
+            let number = span.start.line.to_string();
+            let indent = " ".repeat(number.len());
+            let column = span.start.column - 1;
+            let width = if span.end.line == span.start.line {
+                (span.end.column - span.start.column).max(1)
+            } else {
+                line.chars().count().saturating_sub(column).max(1)
+            };
+            result += &format!("\n{}--> {}:{}", indent, span.start.line, span.start.column);
+            result += &format!("\n{} |", indent);
+            result += &format!("\n{} | {}", number, line);
+            result += &format!("\n{} | {}{}", indent, " ".repeat(column), "^".repeat(width));
+        }
+        let indent = self.span.as_ref().map_or(0, |span| span.start.line.to_string().len());
+        if !self.expected.is_empty() {
+            result += &format!("\n{} = expected one of: {}", " ".repeat(indent), self.expected.join(", "));
+        }
+        if let Some(hint) = &self.hint {
+            result += &format!("\n{} = hint: {}", " ".repeat(indent), hint);
+        }
+        result
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A range in the source of a diagram.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    fn new(source: &str, start: usize, end: usize) -> Self {
+        let start = Location::new(source, start);
+        let end = Location::new(source, end);
+        Self { start, end }
+    }
+}
+
+/// A position in the source of a diagram. `offset` is in bytes, `line` and `column` are
+/// one-based, and `column` counts characters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Location {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    fn new(source: &str, offset: usize) -> Self {
+        let before = source.get(..offset).unwrap_or(source);
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let column = before[line_start..].chars().count() + 1;
+        Self { offset, line, column }
+    }
+}
+
+fn char_len(source: &str, offset: usize) -> usize {
+    source.get(offset..).and_then(|rest| rest.chars().next()).map_or(0, char::len_utf8)
+}
+
+/// Turns a terminal as named by the generated parser into something readable.
+fn describe_token(token: &str) -> String {
+    let description = match token {
+        r##"r#"[-+]?\\d+"#"## | r##"r#"[-+]?\\d+\\.\\d+"#"## => "number",
+        r##"r#"[-+]?\\d+(\\.\\d+)?(rad|deg)"#"## => "angle",
+        r##"r#"[_\\p{ID_Start}][_\\p{ID_Continue}-]*"#"## => "name",
+        r##"r#"[_\\p{ID_Start}][_\\p{ID_Continue}-]*:"#"## => "name followed by `:`",
+        r##"r#"[_\\p{ID_Start}][_\\p{ID_Continue}-]*(::[_\\p{ID_Start}][_\\p{ID_Continue}-]*)+"#"## => "qualified name",
+        r##"r#"([<x]|[o*]?(-x)?)--((x-)?[o*]?|[x>])"#"## => "association",
+        r#""\n""# => "newline",
+        token if token.starts_with("r#\"") => "string",
+        token => {
+            let token = token.strip_prefix('"').and_then(|token| token.strip_suffix('"')).unwrap_or(token);
+            return format!("`{}`", token);
+        }
+    };
+    description.to_string()
+}
+
+/// Describes input that was found where it doesn't belong.
+fn describe_input(input: &str) -> String {
+    match input {
+        "\n" => "newline".to_string(),
+        input => format!("`{}`", input),
+    }
+}
+
+fn describe_tokens(tokens: &[String]) -> Vec<String> {
+    let mut result = Vec::new();
+    for token in tokens {
+        let token = describe_token(token);
+        if !result.contains(&token) {
+            result.push(token);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_diagnose(source: &str, expected: &str) {
+        let error = parser::parse(source).unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(source, &error);
+        assert_eq!(diagnostic.render(source), expected);
+    }
+
+    #[test]
+    fn test_locations() {
+        let source = "class A {\n  «ä» x\n}";
+        assert_eq!(Location::new(source, 0), Location { offset: 0, line: 1, column: 1 });
+        assert_eq!(Location::new(source, 10), Location { offset: 10, line: 2, column: 1 });
+        assert_eq!(Location::new(source, 19), Location { offset: 19, line: 2, column: 7 });
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        test_diagnose(
+            "class A class B",
+            "error: unexpected `class`\n --> 1:9\n  |\n1 | class A class B\n  |         ^^^^^\n  \
+             = expected one of: newline, `<`, `as`, `{`\n  = hint: items and members must each start on a new line",
+        );
+        test_diagnose(
+            "class A {\n  x: \"abc\n}",
+            "error: invalid token\n --> 2:6\n  |\n2 |   x: \"abc\n  |      ^\n  \
+             = hint: strings must be closed on the same line",
+        );
+        test_diagnose(
+            "#[pos(1, x)]\nclass A",
+            "error: unexpected `x`\n --> 1:10\n  |\n1 | #[pos(1, x)]\n  |          ^\n  = expected one of: number",
+        );
+        test_diagnose(
+            "class A {\n  x\n",
+            "error: unexpected end of diagram\n --> 3:1\n  |\n3 | \n  | ^\n  = expected one of: name, \
+             name followed by `:`, newline, `#`, `+`, `-`, `/`, `abstract`, `static`, `{`, `}`, `~`\n  \
+             = hint: a `{` may be missing its closing `}`",
+        );
+    }
+
+    #[test]
+    fn test_user_errors() {
+        test_diagnose(
+            "class A {\n  + - x\n}",
+            "error: a member can only have one visibility\n --> 2:3\n  |\n2 |   + - x\n  |   ^^^",
+        );
+        test_diagnose(
+            "class A {\n  x[3..1]\n}",
+            "error: upper bound is less than lower bound\n --> 2:5\n  |\n2 |   x[3..1]\n  |     ^^^^",
+        );
+        test_diagnose(
+            "A .«use» <T -> X>.> B",
+            "error: template parameter substitutions require «bind»\n --> 1:4\n  |\n1 | A .«use» <T -> X>.> B\n  |    ^^^^^",
+        );
+    }
+}
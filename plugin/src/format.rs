@@ -0,0 +1,460 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cst::{self, Child, Node, NodeKind, Token, TokenKind};
+
+/// How [format] lays out a diagram.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Options {
+    /// the number of spaces per level of nesting in packages and classifier bodies
+    #[serde(default = "default_indent")]
+    pub indent: usize,
+    /// whether the `:` of the attributes of a classifier are put in the same column. Attributes
+    /// separated by a blank line are aligned separately
+    #[serde(default)]
+    pub align_colons: bool,
+    /// whether attributes and operations are sorted by visibility: public, protected, package,
+    /// private, and then those without a visibility
+    #[serde(default)]
+    pub sort_members: bool,
+    /// whether the metas in a `#[...]` block are put in a fixed order, see [META_ORDER]
+    #[serde(default = "default_normalize_metas")]
+    pub normalize_metas: bool,
+}
+
+fn default_indent() -> usize {
+    2
+}
+
+fn default_normalize_metas() -> bool {
+    true
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { indent: default_indent(), align_colons: false, sort_members: false, normalize_metas: default_normalize_metas() }
+    }
+}
+
+/// The order of metas after normalization: those placing the element first, then those shaping
+/// an edge. Unknown metas go last.
+pub const META_ORDER: &[&str] =
+    &["pos", "right-of", "left-of", "above", "below", "align-x", "align-y", "from", "to", "via", "bend", "loop", "route"];
+
+/// Formats the source of a diagram. Every line is indented according to its nesting, runs of
+/// whitespace are collapsed, and there is no whitespace inside brackets or before a comma, but
+/// one after it. Consecutive blank lines are merged, and those at the start or end of a body are
+/// removed. Comments are kept, and formatting the result again doesn't change it.
+///
+/// The source should be a valid diagram; otherwise, it is formatted as far as its structure is
+/// recognizable.
+pub fn format(source: &str, options: &Options) -> String {
+    let tree = cst::parse(source);
+    let mut builder = Builder { options, lines: vec![Line::default()], depth: 0, bodies: Vec::new(), enumerations: Vec::new(), space: false };
+    builder.node(&tree);
+    let Builder { mut lines, enumerations, .. } = builder;
+
+    remove_blank_lines(&mut lines);
+    if options.sort_members {
+        for_each_body(&mut lines, |lines, body| sort_members(lines, enumerations[body]));
+    }
+    if options.align_colons {
+        for_each_body(&mut lines, |lines, _| align_colons(lines));
+    }
+
+    let mut result = String::with_capacity(source.len());
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+        if !line.parts.is_empty() {
+            result.push_str(&" ".repeat(line.depth * options.indent));
+            match &line.aligned {
+                Some(aligned) => result.push_str(aligned),
+                None => result.push_str(&render(&line.parts)),
+            }
+        }
+    }
+    if !lines.is_empty() && source.ends_with(['\n', '\r']) {
+        result.push('\n');
+    }
+    result
+}
+
+/// A token of a line, and whether there was whitespace in front of it.
+#[derive(Debug, Clone, Copy)]
+struct Part<'input> {
+    token: Token<'input>,
+    space: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Line<'input> {
+    depth: usize,
+    /// the classifier body the line is part of, as an index into [Builder::enumerations]
+    body: Option<usize>,
+    parts: Vec<Part<'input>>,
+    /// the line with its `:` aligned with those of the surrounding members
+    aligned: Option<String>,
+}
+
+impl Line<'_> {
+    fn is_blank(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    fn is_comment(&self) -> bool {
+        !self.is_blank() && self.parts.iter().all(|part| part.token.kind.is_trivia())
+    }
+}
+
+/// Splits the tree into lines of tokens, keeping track of nesting.
+struct Builder<'a, 'input> {
+    options: &'a Options,
+    lines: Vec<Line<'input>>,
+    depth: usize,
+    /// the classifier bodies the current token is in, innermost last; `None` for packages
+    bodies: Vec<Option<usize>>,
+    /// for every classifier body, whether it is that of an enumeration
+    enumerations: Vec<bool>,
+    space: bool,
+}
+
+impl<'input> Builder<'_, 'input> {
+    fn node(&mut self, node: &Node<'input>) {
+        for child in &node.children {
+            match child {
+                Child::Node(child) if child.kind == NodeKind::Metas && self.options.normalize_metas => {
+                    let tokens: Vec<_> = child.tokens().copied().collect();
+                    let tokens = normalize_metas(&tokens).unwrap_or(tokens);
+                    tokens.into_iter().for_each(|token| self.token(token));
+                },
+                Child::Node(child) if child.kind == NodeKind::Body => {
+                    let body = (node.kind == NodeKind::Classifier).then(|| {
+                        let is_enumeration = node.children.iter().any(|child| {
+                            matches!(child, Child::Token(token) if token.text == "enumeration" && token.kind == TokenKind::Keyword)
+                        });
+                        self.enumerations.push(is_enumeration);
+                        self.enumerations.len() - 1
+                    });
+                    self.body(child, body);
+                },
+                Child::Node(child) => self.node(child),
+                &Child::Token(token) => self.token(token),
+            }
+        }
+    }
+
+    fn body(&mut self, node: &Node<'input>, body: Option<usize>) {
+        let mut open = false;
+        for child in &node.children {
+            match child {
+                &Child::Token(token) if !open && token.text == "{" => {
+                    self.token(token);
+                    self.depth += 1;
+                    self.bodies.push(body);
+                    open = true;
+                },
+                &Child::Token(token) if open && token.text == "}" => {
+                    self.depth -= 1;
+                    self.bodies.pop();
+                    open = false;
+                    self.token(token);
+                },
+                Child::Node(child) => self.node(child),
+                &Child::Token(token) => self.token(token),
+            }
+        }
+        // an unclosed body ends with the input
+        if open {
+            self.depth -= 1;
+            self.bodies.pop();
+        }
+    }
+
+    fn token(&mut self, token: Token<'input>) {
+        match token.kind {
+            TokenKind::Newline => {
+                self.lines.push(Line::default());
+                self.space = false;
+            },
+            TokenKind::Whitespace => self.space = true,
+            _ => {
+                let line = self.lines.last_mut().expect("there is always a line");
+                if line.parts.is_empty() {
+                    line.depth = self.depth;
+                    line.body = self.bodies.last().copied().flatten();
+                }
+                line.parts.push(Part { token, space: self.space && !line.parts.is_empty() });
+                self.space = false;
+            },
+        }
+    }
+}
+
+/// Puts the metas of a `#[...]` block in the order of [META_ORDER], separated by `", "`. Returns
+/// `None` if the block is malformed.
+fn normalize_metas<'input>(tokens: &[Token<'input>]) -> Option<Vec<Token<'input>>> {
+    let (first, rest) = tokens.split_first()?;
+    let (last, inner) = rest.split_last()?;
+    if first.text != "#[" || last.text != "]" {
+        return None;
+    }
+
+    let mut entries = vec![Vec::new()];
+    let mut depth = 0usize;
+    for &token in inner {
+        match token.text {
+            "(" | "[" => depth += 1,
+            ")" | "]" => depth = depth.checked_sub(1)?,
+            "," if depth == 0 => {
+                entries.push(Vec::new());
+                continue;
+            },
+            _ => {},
+        }
+        entries.last_mut().expect("there is always an entry").push(token);
+    }
+    for entry in &mut entries {
+        let start = entry.iter().position(|token| token.kind != TokenKind::Whitespace).unwrap_or(entry.len());
+        let end = entry.iter().rposition(|token| token.kind != TokenKind::Whitespace).map_or(start, |index| index + 1);
+        *entry = entry[start..end].to_vec();
+    }
+    entries.retain(|entry| !entry.is_empty());
+    let rank = |entry: &Vec<Token<'_>>| {
+        let name = entry.iter().find(|token| !token.kind.is_trivia()).map(|token| token.text);
+        META_ORDER.iter().position(|&meta| Some(meta) == name).unwrap_or(META_ORDER.len())
+    };
+    entries.sort_by_key(rank);
+
+    let synthetic = |kind, text| Token { kind, text, start: 0, end: 0 };
+    let mut result = vec![*first];
+    for (index, entry) in entries.into_iter().enumerate() {
+        if index > 0 {
+            result.push(synthetic(TokenKind::Punctuation, ","));
+            result.push(synthetic(TokenKind::Whitespace, " "));
+        }
+        result.extend(entry);
+    }
+    result.push(*last);
+    Some(result)
+}
+
+/// Merges consecutive blank lines, and removes those at the start and end of the source and of
+/// bodies.
+fn remove_blank_lines(lines: &mut Vec<Line<'_>>) {
+    let mut result: Vec<Line<'_>> = Vec::with_capacity(lines.len());
+    for line in lines.drain(..) {
+        if line.is_blank() {
+            let after_open = result.last().is_none_or(|previous| {
+                previous.is_blank() || previous.parts.last().is_some_and(|part| part.token.text == "{")
+            });
+            if after_open {
+                continue;
+            }
+        } else if line.parts[0].token.text == "}" && result.last().is_some_and(Line::is_blank) {
+            result.pop();
+        }
+        result.push(line);
+    }
+    while result.last().is_some_and(Line::is_blank) {
+        result.pop();
+    }
+    *lines = result;
+}
+
+/// Calls `f` for every run of lines in the same classifier body.
+fn for_each_body<'input>(lines: &mut [Line<'input>], mut f: impl FnMut(&mut [Line<'input>], usize)) {
+    let mut start = 0;
+    while start < lines.len() {
+        let Some(body) = lines[start].body else {
+            start += 1;
+            continue;
+        };
+        // blank lines don't know their body, but a body's lines are contiguous
+        let end = (start..lines.len())
+            .take_while(|&index| lines[index].body == Some(body) || lines[index].is_blank())
+            .last()
+            .map_or(start, |index| index + 1);
+        f(&mut lines[start..end], body);
+        start = end;
+    }
+}
+
+/// Sorts the members in each group of lines separated by blank lines. Comment lines stay with the
+/// member that follows them.
+fn sort_members(lines: &mut [Line<'_>], is_enumeration: bool) {
+    for group in lines.split_mut(Line::is_blank) {
+        let mut entries = Vec::new();
+        let mut start = 0;
+        for (index, line) in group.iter().enumerate() {
+            if !line.is_comment() {
+                entries.push((member_key(&line.parts, is_enumeration), group[start..=index].to_vec()));
+                start = index + 1;
+            }
+        }
+        let trailing = group[start..].to_vec();
+        entries.sort_by_key(|(key, _)| *key);
+        let sorted = entries.into_iter().flat_map(|(_, lines)| lines).chain(trailing);
+        for (line, sorted) in group.iter_mut().zip(sorted.collect::<Vec<_>>()) {
+            *line = sorted;
+        }
+    }
+}
+
+/// The order of a member: enumeration literals first, then attributes, then operations, as the
+/// grammar requires; each by visibility.
+fn member_key(parts: &[Part<'_>], is_enumeration: bool) -> (u8, u8) {
+    let texts: Vec<_> = parts.iter().filter(|part| !part.token.kind.is_trivia()).map(|part| part.token.text).collect();
+    let mut visibility = None;
+    let mut index = 0;
+    loop {
+        match texts[index..] {
+            [text @ ("+" | "#" | "~" | "-"), ..] => visibility = visibility.or(Some(text)),
+            ["static" | "abstract", ..] => {},
+            ["{", "static" | "abstract", "}", ..] => index += 2,
+            _ => break,
+        }
+        index += 1;
+    }
+    let is_marked = index > 0 || texts.get(index) == Some(&"/");
+    let is_operation = texts.get(index + 1) == Some(&"(");
+    let category = match () {
+        _ if is_enumeration && !is_marked => 0,
+        _ if is_operation => 2,
+        _ => 1,
+    };
+    let visibility = match visibility {
+        Some("+") => 0,
+        Some("#") => 1,
+        Some("~") => 2,
+        Some("-") => 3,
+        _ => 4,
+    };
+    (category, visibility)
+}
+
+/// Aligns the `:` of the attributes in each group of lines separated by blank lines.
+fn align_colons(lines: &mut [Line<'_>]) {
+    for group in lines.split_mut(Line::is_blank) {
+        let colons: Vec<_> = group
+            .iter()
+            .map(|line| split_at_colon(&line.parts).filter(|_| member_key(&line.parts, false).0 != 2))
+            .collect();
+        let Some(width) = colons.iter().flatten().map(|(prefix, _)| prefix.chars().count()).max() else {
+            continue;
+        };
+        for (line, colon) in group.iter_mut().zip(colons) {
+            if let Some((prefix, rest)) = colon {
+                let padding = " ".repeat(width - prefix.chars().count());
+                let separator = if rest.is_empty() { "" } else { " " };
+                line.aligned = Some(format!("{prefix}{padding}:{separator}{rest}"));
+            }
+        }
+    }
+}
+
+/// The parts of a member before and after its type's `:`, rendered; `None` if it has no type.
+fn split_at_colon(parts: &[Part<'_>]) -> Option<(String, String)> {
+    let mut depth = 0usize;
+    for (index, part) in parts.iter().enumerate() {
+        match part.token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            ":" if depth == 0 => return Some((render(&parts[..index]), render(&parts[index + 1..]))),
+            text if depth == 0 && part.token.kind == TokenKind::Name && text.ends_with(':') => {
+                let mut prefix = render(&parts[..=index]);
+                prefix.pop();
+                return Some((prefix, render(&parts[index + 1..])));
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Joins parts, putting a space where there was whitespace in the source, except inside brackets
+/// and in front of commas. Commas are always followed by a space.
+fn render(parts: &[Part<'_>]) -> String {
+    let mut result = String::new();
+    let mut previous = None;
+    for part in parts {
+        let text = part.token.text;
+        if let Some(previous) = previous {
+            let space = match (previous, text) {
+                (_, ")" | "]" | ",") | ("(" | "[" | "#[", _) => false,
+                (",", _) => true,
+                _ => part.space,
+            };
+            if space {
+                result.push(' ');
+            }
+        }
+        result.push_str(text);
+        previous = Some(text);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn test_format(source: &str, options: &Options, expected: &str) {
+        let formatted = format(source, options);
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted, options), formatted, "formatting is not idempotent");
+        let diagram = parser::parse(&formatted).unwrap();
+        // apart from sorting members, formatting doesn't change the diagram
+        if !options.sort_members {
+            assert_eq!(diagram.to_string(), parser::parse(source).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn test_format_layout() {
+        let options = Options::default();
+        test_format(
+            "\n\n// classes\nclass A {\n\n        - x : Int\n+   f( a:Int ,b: Int )\n\n}\n\n\n\npackage p {\n\n\tinterface I   /* I */\n  }\nA --   p::I\n",
+            &options,
+            "// classes\nclass A {\n  - x : Int\n  + f(a:Int, b: Int)\n}\n\npackage p {\n  interface I /* I */\n}\nA -- p::I\n",
+        );
+        test_format(
+            "enumeration E { X; Y }\n#[ pos( 0,1 ) ]\nclass B",
+            &Options { indent: 4, ..options },
+            "enumeration E { X; Y }\n#[pos(0, 1)]\nclass B",
+        );
+        test_format("", &Options::default(), "");
+    }
+
+    #[test]
+    fn test_format_metas() {
+        test_format(
+            "#[bend(10deg), via((1, 0)), from(south)]\nA -- B",
+            &Options::default(),
+            "#[from(south), via((1, 0)), bend(10deg)]\nA -- B",
+        );
+        test_format("#[route(straight) , pos(0, 0)] class A", &Options::default(), "#[pos(0, 0), route(straight)] class A");
+        test_format(
+            "#[bend(10deg), via((1, 0))]\nA -- B",
+            &Options { normalize_metas: false, ..Options::default() },
+            "#[bend(10deg), via((1, 0))]\nA -- B",
+        );
+    }
+
+    #[test]
+    fn test_format_members() {
+        let options = Options { align_colons: true, sort_members: true, ..Options::default() };
+        test_format(
+            "class A {\n  - id: Int\n  /* the name */\n  + name: String\n  count: Int\n\n  ~ a-long-name: X {readOnly}\n  + b: Y\n  # f(x: Int): Bool\n  + g()\n}",
+            &options,
+            "class A {\n  /* the name */\n  + name: String\n  - id  : Int\n  count : Int\n\n  + b          : Y\n  ~ a-long-name: X {readOnly}\n  + g()\n  # f(x: Int): Bool\n}",
+        );
+        test_format(
+            "enumeration E {\n  B\n  A\n  - x: Int\n  + y: Int\n}",
+            &options,
+            "enumeration E {\n  B\n  A\n  + y: Int\n  - x: Int\n}",
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use crate::model::{Classifier, Diagram, Edge, Meta, MetaSpan, Note, Package};
+
+/// Writes the layout of a diagram back into its source, so that later edits don't move elements
+/// around. The diagram must have been parsed from `source` and laid out; the metas of each
+/// classifier, edge and note, such as the computed `#[pos]` and `#[via]`, replace the `#[...]` block
+/// in front of it, or are inserted if there is none. Everything else in the source, including
+/// comments and formatting, is kept as is.
+pub fn freeze(source: &str, diagram: &Diagram<'_>) -> String {
+    let mut edits = Vec::new();
+    collect_edits(source, &mut edits, &diagram.classifiers, &diagram.edges, &diagram.notes, &diagram.packages);
+    edits.sort_by_key(|&(start, _, _)| start);
+
+    let mut result = String::with_capacity(source.len());
+    let mut offset = 0;
+    for (start, end, replacement) in edits {
+        result.push_str(&source[offset..start]);
+        result.push_str(&replacement);
+        offset = end;
+    }
+    result.push_str(&source[offset..]);
+    result
+}
+
+fn collect_edits(
+    source: &str,
+    edits: &mut Vec<(usize, usize, String)>,
+    classifiers: &[Classifier<'_>],
+    edges: &[Edge<'_>],
+    notes: &[Note<'_>],
+    packages: &[Package<'_>],
+) {
+    let elements = classifiers
+        .iter()
+        .map(|classifier| (&classifier.meta, classifier.span))
+        .chain(edges.iter().map(|edge| (&edge.meta, edge.span)))
+        .chain(notes.iter().map(|note| (&note.meta, note.span)));
+    for (meta, span) in elements {
+        edits.extend(edit(source, meta, span));
+    }
+    for package in packages {
+        collect_edits(source, edits, &package.classifiers, &package.edges, &package.notes, &package.packages);
+    }
+}
+
+/// The replacement for an element's `#[...]` block, or `None` if there is nothing to write.
+fn edit(source: &str, meta: &BTreeMap<&str, Meta<'_>>, span: MetaSpan) -> Option<(usize, usize, String)> {
+    let metas = format_metas(meta);
+    match span.metas {
+        Some((start, end)) => Some((start, end, metas)),
+        None if metas.is_empty() => None,
+        None => {
+            // put the metas on their own line if the element starts a line, otherwise in front of it
+            let line_start = source[..span.start].rfind('\n').map_or(0, |index| index + 1);
+            let indent = &source[line_start..span.start];
+            let separator = if indent.trim().is_empty() { format!("\n{}", indent) } else { " ".to_string() };
+            Some((span.start, span.start, metas + &separator))
+        }
+    }
+}
+
+fn format_metas(meta: &BTreeMap<&str, Meta<'_>>) -> String {
+    // computed coordinates are rounded, so that they don't show floating point noise
+    let round = |x: f32| (x * 1000.0).round() / 1000.0;
+    let metas: Vec<_> = meta
+        .values()
+        .map(|meta| match meta {
+            &Meta::Position(x, y) => Meta::Position(round(x), round(y)).to_string(),
+            Meta::Via(points) => Meta::Via(points.iter().map(|&(x, y)| (round(x), round(y))).collect()).to_string(),
+            meta => meta.to_string(),
+        })
+        .collect();
+    if metas.is_empty() {
+        String::new()
+    } else {
+        format!("#[{}]", metas.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{self, Options};
+    use crate::parser;
+
+    fn test_freeze(source: &str, expected: &str) {
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout::layout(&mut diagram, &Options::default());
+        assert_eq!(freeze(source, &diagram), expected);
+    }
+
+    #[test]
+    fn test_freeze_positions() {
+        test_freeze("class A\nclass B\nB --|> A", "#[pos(0, 0)]\nclass A\n#[pos(0, 1)]\nclass B\nB --|> A");
+        test_freeze(
+            "// the base\nclass A  /* comment */\n\n#[pos(3, 0)] class B\npackage p {\n    /* nested */ class C\n}",
+            "// the base\n#[pos(4, 0)]\nclass A  /* comment */\n\n#[pos(3, 0)] class B\npackage p {\n    /* nested */ #[pos(5, 0)] class C\n}",
+        );
+        test_freeze(
+            "#[pos(0, 0)]\nclass A\n#[right-of(A, 2)]\nclass B\n#[route(straight)]\nA -- B\nA -- A",
+            "#[pos(0, 0)]\nclass A\n#[pos(2, 0)]\nclass B\n#[route(straight)]\nA -- B\n#[bend(130deg), loop(90deg)]\nA -- A",
+        );
+        // anchored points and sides stay relative to their classifiers
+        test_freeze(
+            "#[pos(0, 0)]\nclass A\nclass B\n#[via(A.east + (1, 0)), to(north)]\nA -- B",
+            "#[pos(0, 0)]\nclass A\n#[pos(1, 0)]\nclass B\n#[to(north), via(A.east + (1, 0))]\nA -- B",
+        );
+    }
+
+    #[test]
+    fn test_freeze_notes() {
+        test_freeze(
+            "class A\nnote on A \"a\"\n  note \"b\"\n#[pos(3, 3)] note \"c\"",
+            "#[pos(0, 0)]\nclass A\n#[pos(1, 0)]\nnote on A \"a\"\n  #[pos(0, 4)]\n  note \"b\"\n#[pos(3, 3)] note \"c\"",
+        );
+    }
+
+    #[test]
+    fn test_freeze_routes() {
+        let source = "#[pos(0, 0)]\nclass A\n#[pos(1, 0)]\nclass B\n#[pos(2, 0)]\nclass C\n  A -- C";
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout::layout(&mut diagram, &Options::default());
+        let frozen = freeze(source, &diagram);
+        assert!(frozen.contains("\n  #[via(("), "{}", frozen);
+
+        // freezing is idempotent
+        let mut diagram = parser::parse(&frozen).unwrap();
+        diagram.resolve_references();
+        layout::layout(&mut diagram, &Options::default());
+        assert_eq!(freeze(&frozen, &diagram), frozen);
+    }
+}
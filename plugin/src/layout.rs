@@ -0,0 +1,755 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostic::Diagnostic;
+use crate::model::{self, Classifier, Diagram, Direction, Edge, EdgeKind, Meta, MetaSpan, Note, NoteAnchor, Package, Routing, Side};
+
+use routing::Rect;
+
+mod constraints;
+mod force;
+mod layered;
+mod routing;
+
+/// How a diagram is laid out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Options {
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// how edges without a `#[route]` are routed
+    #[serde(default)]
+    pub routing: Routing,
+    /// the widths and heights of classifiers by qualified id, in grid units. Classifiers without
+    /// a size are assumed to be [DEFAULT_SIZE]
+    #[serde(default)]
+    pub sizes: BTreeMap<String, (f32, f32)>,
+}
+
+/// the estimated width and height of a node, in grid units
+pub const DEFAULT_SIZE: (f32, f32) = (0.5, 0.5);
+
+/// The sizes of the rendered nodes, as measured by Typst.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Measurements {
+    /// the widths and heights of classifiers by qualified id, in points
+    #[serde(default)]
+    pub sizes: BTreeMap<String, (f32, f32)>,
+    /// the gap between rows and columns of the grid, in points
+    #[serde(default)]
+    pub spacing: f32,
+}
+
+impl Measurements {
+    /// Converts the measured sizes into grid units. Like fletcher's grid, each column is as wide
+    /// as its widest node plus the spacing, and each row as high as its highest node plus the
+    /// spacing; nodes are assigned to the column and row closest to their position.
+    pub fn grid_sizes(&self, positions: &BTreeMap<String, Point>) -> BTreeMap<String, (f32, f32)> {
+        let cell = |(x, y): Point| (x.round() as i64, y.round() as i64);
+        let mut columns: BTreeMap<i64, f32> = BTreeMap::new();
+        let mut rows: BTreeMap<i64, f32> = BTreeMap::new();
+        for (id, &(width, height)) in &self.sizes {
+            let Some(&position) = positions.get(id) else { continue };
+            let (column, row) = cell(position);
+            let column = columns.entry(column).or_default();
+            *column = column.max(width);
+            let row = rows.entry(row).or_default();
+            *row = row.max(height);
+        }
+        self.sizes
+            .iter()
+            .filter_map(|(id, &(width, height))| {
+                let (column, row) = cell(*positions.get(id)?);
+                let scale = |size: f32, extent: f32| {
+                    let extent = extent + self.spacing;
+                    if extent > 0.0 { size / extent } else { 0.0 }
+                };
+                Some((id.clone(), (scale(width, columns[&column]), scale(height, rows[&row]))))
+            })
+            .collect()
+    }
+
+    /// Returns how many grid cells each classifier takes up when placing them, at least one along
+    /// each axis. A cell is as large as the median classifier plus the spacing, so that a
+    /// classifier much wider or higher than the others claims the cells next to it, and its
+    /// neighbours are moved away instead of being drawn on top of it.
+    pub fn cells(&self) -> BTreeMap<String, (f32, f32)> {
+        let median = |extent: fn(&(f32, f32)) -> f32| {
+            let mut extents: Vec<f32> = self.sizes.values().map(extent).collect();
+            extents.sort_by(f32::total_cmp);
+            extents.get(extents.len() / 2).map_or(0.0, |extent| extent + self.spacing)
+        };
+        let (column, row) = (median(|size| size.0), median(|size| size.1));
+        let cells = |size: f32, cell: f32| if cell > 0.0 { ((size + self.spacing) / cell).max(1.0) } else { 1.0 };
+        self.sizes.iter().map(|(id, &(width, height))| (id.clone(), (cells(width, column), cells(height, row)))).collect()
+    }
+}
+
+/// The algorithm used for placing classifiers that don't have a position.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    /// layers with supertypes above their subtypes; suits inheritance hierarchies
+    #[default]
+    Layered,
+    /// stress majorization, with related classifiers close together; suits domain models
+    Force,
+}
+
+/// A position in fletcher's grid coordinates, with `y` pointing down.
+pub type Point = (f32, f32);
+
+/// The classifiers of a diagram and the edges between them, as seen by the layout algorithms.
+/// Nodes are identified by their index; edges whose endpoints can't be resolved are left out.
+#[derive(Debug, Default)]
+pub struct Graph {
+    /// the qualified ids of the classifiers
+    pub ids: Vec<String>,
+    /// the positions the user fixed with `#[pos]`
+    pub fixed: Vec<Option<Point>>,
+    /// where the classifiers are in the source, for diagnostics
+    pub spans: Vec<MetaSpan>,
+    pub edges: Vec<GraphEdge>,
+    /// relative positions such as `right-of`, in source order
+    pub constraints: Vec<Constraint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphEdge {
+    /// for hierarchy edges, the more general classifier
+    pub from: usize,
+    /// for hierarchy edges, the more specific classifier
+    pub to: usize,
+    /// whether this is a generalization or realization
+    pub hierarchy: bool,
+}
+
+/// A relative position along one axis: `node`'s coordinate is `offset` more than `reference`'s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub node: usize,
+    pub reference: usize,
+    pub axis: Axis,
+    pub offset: f32,
+    /// the meta this constraint comes from, e.g. "`right-of(A)` of `B`"
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+impl Graph {
+    /// Builds the graph of a diagram whose references have been resolved.
+    pub fn new(diagram: &Diagram<'_>) -> Self {
+        let mut graph = Self::default();
+        visit_classifiers(&mut graph, "", &diagram.classifiers, &diagram.packages);
+
+        let indices: BTreeMap<_, _> = graph.ids.iter().enumerate().map(|(i, id)| (id.clone(), i)).collect();
+        let mut metas = Vec::new();
+        collect_metas(&mut metas, &diagram.classifiers, &diagram.packages);
+        for (node, meta) in metas.into_iter().enumerate().flat_map(|(i, meta)| meta.values().map(move |meta| (i, meta))) {
+            let source = format!("`{}` of `{}`", meta, graph.ids[node]);
+            let mut constrain = |reference: &str, axis, offset| {
+                if let Some(&reference) = indices.get(reference) {
+                    let source = source.clone();
+                    graph.constraints.push(Constraint { node, reference, axis, offset, source });
+                }
+            };
+            match meta {
+                Meta::RightOf(reference, distance) => {
+                    constrain(reference, Axis::X, *distance);
+                    constrain(reference, Axis::Y, 0.0);
+                }
+                Meta::LeftOf(reference, distance) => {
+                    constrain(reference, Axis::X, -distance);
+                    constrain(reference, Axis::Y, 0.0);
+                }
+                Meta::Above(reference, distance) => {
+                    constrain(reference, Axis::X, 0.0);
+                    constrain(reference, Axis::Y, -distance);
+                }
+                Meta::Below(reference, distance) => {
+                    constrain(reference, Axis::X, 0.0);
+                    constrain(reference, Axis::Y, *distance);
+                }
+                Meta::AlignX(references) => references.iter().for_each(|reference| constrain(reference, Axis::X, 0.0)),
+                Meta::AlignY(references) => references.iter().for_each(|reference| constrain(reference, Axis::Y, 0.0)),
+                _ => {}
+            }
+        }
+
+        for edge in edges(&diagram.edges, &diagram.packages) {
+            let (Some(&a), Some(&b)) = (indices.get(edge.a.as_ref()), indices.get(edge.b.as_ref())) else {
+                continue;
+            };
+            let graph_edge = match edge.kind {
+                EdgeKind::Generalization { direction } | EdgeKind::Realization { direction } => {
+                    let (from, to) = match direction {
+                        Direction::AToB => (b, a),
+                        Direction::BToA => (a, b),
+                    };
+                    GraphEdge { from, to, hierarchy: true }
+                }
+                _ => GraphEdge { from: a, to: b, hierarchy: false },
+            };
+            graph.edges.push(graph_edge);
+        }
+        graph
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+fn visit_classifiers(graph: &mut Graph, prefix: &str, classifiers: &[Classifier<'_>], packages: &[Package<'_>]) {
+    for classifier in classifiers {
+        graph.ids.push(model::qualify(prefix, classifier.id.unwrap_or(&classifier.name)));
+        graph.fixed.push(position(&classifier.meta));
+        graph.spans.push(classifier.span);
+    }
+    for package in packages {
+        let prefix = model::qualify(prefix, package.name);
+        visit_classifiers(graph, &prefix, &package.classifiers, &package.packages);
+    }
+}
+
+fn collect_metas<'a, 'input>(
+    metas: &mut Vec<&'a BTreeMap<&'input str, Meta<'input>>>,
+    classifiers: &'a [Classifier<'input>],
+    packages: &'a [Package<'input>],
+) {
+    metas.extend(classifiers.iter().map(|classifier| &classifier.meta));
+    for package in packages {
+        collect_metas(metas, &package.classifiers, &package.packages);
+    }
+}
+
+fn edges<'a, 'input>(edges: &'a [Edge<'input>], packages: &'a [Package<'input>]) -> Vec<&'a Edge<'input>> {
+    let mut result: Vec<_> = edges.iter().collect();
+    for package in packages {
+        result.extend(self::edges(&package.edges, &package.packages));
+    }
+    result
+}
+
+fn edges_mut<'a, 'input>(edges: &'a mut [Edge<'input>], packages: &'a mut [Package<'input>]) -> Vec<&'a mut Edge<'input>> {
+    let mut result: Vec<_> = edges.iter_mut().collect();
+    for package in packages {
+        result.extend(edges_mut(&mut package.edges, &mut package.packages));
+    }
+    result
+}
+
+fn position(meta: &BTreeMap<&str, Meta<'_>>) -> Option<Point> {
+    match meta.get("pos") {
+        Some(&Meta::Position(x, y)) => Some((x, y)),
+        _ => None,
+    }
+}
+
+/// Assigns positions to all classifiers and notes that don't have a `#[pos]` yet. Positions the
+/// user specified are left as they are, and relative positions such as `#[right-of(A)]` are
+/// replaced by the absolute positions they result in. Relative positions that contradict others
+/// are ignored; see [check]. The diagram's references must have been resolved.
+pub fn layout(diagram: &mut Diagram<'_>, options: &Options) {
+    let (positions, occupied) = place(diagram, options, &BTreeMap::new());
+    shape_edges(diagram);
+    route(diagram, options.routing, &options.sizes, &positions, &occupied);
+}
+
+/// Like [layout], but places classifiers and routes edges using the sizes of the rendered nodes.
+/// This is the second phase of laying out a diagram: Typst renders the classifiers, measures them,
+/// and passes the sizes back, so that large classifiers get more room and edges are routed around
+/// the actual boxes. Measured sizes take precedence over the sizes in the options.
+pub fn arrange(diagram: &mut Diagram<'_>, options: &Options, measurements: &Measurements) {
+    let (positions, occupied) = place(diagram, options, &measurements.cells());
+    shape_edges(diagram);
+    let mut sizes = options.sizes.clone();
+    sizes.extend(measurements.grid_sizes(&positions));
+    route(diagram, options.routing, &sizes, &positions, &occupied);
+}
+
+/// Places classifiers and notes, returning the positions of all classifiers by qualified id and
+/// the positions of all nodes, classifiers first. `cells` are the numbers of grid cells classifiers
+/// take up; see [Measurements::cells]. Classifiers without them take up one cell.
+fn place(
+    diagram: &mut Diagram<'_>,
+    options: &Options,
+    cells: &BTreeMap<String, (f32, f32)>,
+) -> (BTreeMap<String, Point>, Vec<Point>) {
+    let mut graph = Graph::new(diagram);
+    let (mut coordinates, _) = constraints::solve(&graph);
+    graph.fixed = constraints::fixed(&mut coordinates);
+    let positions = match options.algorithm {
+        Algorithm::Layered => layered::layout(&graph),
+        Algorithm::Force => force::layout(&graph),
+    };
+    let (mut xs, mut ys): (Vec<_>, Vec<_>) = positions.into_iter().unzip();
+    let [x_coordinates, y_coordinates] = &mut coordinates;
+    x_coordinates.apply(&mut xs);
+    y_coordinates.apply(&mut ys);
+    let cells: Vec<_> = graph.ids.iter().map(|id| cells.get(id).copied().unwrap_or((1.0, 1.0))).collect();
+    constraints::separate(&mut coordinates, &mut xs, &mut ys, &cells);
+    let positions = xs.into_iter().zip(ys);
+
+    let positions: BTreeMap<_, _> = graph.ids.into_iter().zip(positions).collect();
+    place_classifiers(&positions, "", &mut diagram.classifiers, &mut diagram.packages);
+
+    let mut occupied: Vec<Point> = positions.values().copied().collect();
+    collect_note_positions(&mut occupied, &diagram.notes, &diagram.packages);
+    place_notes(&positions, &mut occupied, &mut diagram.notes, &mut diagram.packages);
+    (positions, occupied)
+}
+
+/// the bend of self-associations, which makes fletcher draw them as loops
+const LOOP_BEND: f32 = 130.0 * PI / 180.0;
+/// the directions in which the self-associations of a classifier leave it, in turn
+const LOOP_ANGLES: [f32; 4] = [PI / 2.0, 0.0, PI, -PI / 2.0];
+/// the difference in bend between neighboring parallel edges
+const FAN_BEND: f32 = 30.0 * PI / 180.0;
+
+/// Draws self-associations as loops, and fans out parallel edges between the same classifiers so
+/// that they don't overlap. Loops get a `bend` and a `loop` angle unless the user specified them;
+/// parallel edges with a `bend`, `via`, `route`, `from` or `to` are left alone.
+fn shape_edges(diagram: &mut Diagram<'_>) {
+    let mut edges = edges_mut(&mut diagram.edges, &mut diagram.packages);
+    let explicit = |edge: &Edge<'_>| ["bend", "via", "route", "from", "to"].iter().any(|key| edge.meta.contains_key(key));
+
+    let mut groups: BTreeMap<(&str, &str), Vec<usize>> = BTreeMap::new();
+    let endpoints: Vec<_> = edges.iter().map(|edge| (edge.a.to_string(), edge.b.to_string())).collect();
+    for (i, (a, b)) in endpoints.iter().enumerate() {
+        if a != b && explicit(edges[i]) {
+            continue;
+        }
+        let key = if a <= b { (a.as_str(), b.as_str()) } else { (b.as_str(), a.as_str()) };
+        groups.entry(key).or_default().push(i);
+    }
+
+    for ((a, b), group) in groups {
+        if a == b {
+            for (k, &i) in group.iter().enumerate() {
+                edges[i].meta.entry("bend").or_insert(Meta::Bend(LOOP_BEND));
+                edges[i].meta.entry("loop").or_insert(Meta::Loop(LOOP_ANGLES[k % LOOP_ANGLES.len()]));
+            }
+        } else if group.len() > 1 {
+            let middle = (group.len() - 1) as f32 / 2.0;
+            for (k, &i) in group.iter().enumerate() {
+                // bends are relative to the edge's direction, so edges in the other direction are
+                // bent the other way
+                let bend = (k as f32 - middle) * FAN_BEND;
+                let bend = if endpoints[i].0 == a { bend } else { -bend };
+                if bend != 0.0 {
+                    edges[i].meta.insert("bend", Meta::Bend(bend));
+                }
+            }
+        }
+    }
+}
+
+fn route(
+    diagram: &mut Diagram<'_>,
+    routing: Routing,
+    sizes: &BTreeMap<String, (f32, f32)>,
+    positions: &BTreeMap<String, Point>,
+    occupied: &[Point],
+) {
+    let rect = |id: Option<&String>, center: Point| {
+        let (width, height) = id.and_then(|id| sizes.get(id)).copied().unwrap_or(DEFAULT_SIZE);
+        Rect { center, half: (width / 2.0, height / 2.0) }
+    };
+    let mut rects: Vec<_> = positions.iter().map(|(id, &center)| (Some(id.as_str()), rect(Some(id), center))).collect();
+    rects.extend(occupied[positions.len()..].iter().map(|&center| (None, rect(None, center))));
+    let mut edges = edges_mut(&mut diagram.edges, &mut diagram.packages);
+    resolve_anchors(&rects, &mut edges);
+    route_edges(&rects, routing, &mut edges);
+}
+
+/// how far outside a classifier's side the point an edge is led through with `from` or `to` is
+const PORT_DISTANCE: f32 = 0.25;
+
+/// Resolves `via` points that are relative to classifiers, as well as the `from` and `to` sides of
+/// edges, into absolute points. Points relative to unknown classifiers are left out.
+fn resolve_anchors(rects: &[(Option<&str>, Rect)], edges: &mut [&mut Edge<'_>]) {
+    let find = |id: &str| rects.iter().find(|(rect_id, _)| *rect_id == Some(id)).map(|&(_, rect)| rect);
+    let side = |rect: Rect, side: Side, distance: f32| {
+        let ((x, y), (w, h), (dx, dy)) = (rect.center, rect.half, side.direction());
+        (x + dx * (w + distance), y + dy * (h + distance))
+    };
+
+    for edge in edges {
+        let (a, b) = (find(&edge.a), find(&edge.b));
+        for meta in edge.meta.values_mut() {
+            match meta {
+                Meta::AnchoredVia(anchors, resolved) => {
+                    *resolved = anchors
+                        .iter()
+                        .filter_map(|anchor| {
+                            let (dx, dy) = anchor.offset;
+                            let Some(reference) = &anchor.reference else {
+                                return Some((dx, dy));
+                            };
+                            let rect = find(reference)?;
+                            let (x, y) = anchor.side.map_or(rect.center, |anchor_side| side(rect, anchor_side, 0.0));
+                            Some((x + dx, y + dy))
+                        })
+                        .collect();
+                }
+                Meta::From(from, point) => *point = a.map(|rect| side(rect, *from, PORT_DISTANCE)),
+                Meta::To(to, point) => *point = b.map(|rect| side(rect, *to, PORT_DISTANCE)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Adds `via` points to edges that need to be routed around other nodes. Edges that already have
+/// `via`, `bend`, `from` or `to` are left alone. The `route` metas are kept, so that they survive
+/// [crate::freeze].
+fn route_edges(rects: &[(Option<&str>, Rect)], routing: Routing, edges: &mut [&mut Edge<'_>]) {
+    for edge in edges {
+        let routing = match edge.meta.get("route") {
+            Some(&Meta::Route(routing)) => routing,
+            _ => routing,
+        };
+        if edge.a == edge.b || ["via", "bend", "from", "to"].iter().any(|key| edge.meta.contains_key(key)) {
+            continue;
+        }
+        let find = |id: &str| rects.iter().find(|(rect_id, _)| *rect_id == Some(id)).map(|&(_, rect)| rect);
+        let (Some(source), Some(target)) = (find(&edge.a), find(&edge.b)) else {
+            continue;
+        };
+        let obstacles: Vec<_> = rects
+            .iter()
+            .filter(|(id, _)| id.is_none_or(|id| id != edge.a && id != edge.b))
+            .map(|&(_, rect)| rect)
+            .collect();
+        if let Some(via) = routing::route(&source, &target, &obstacles, routing) {
+            edge.meta.insert("via", Meta::Via(via));
+        }
+    }
+}
+
+/// Checks the relative positions of a diagram for contradictions, and for placing classifiers on
+/// top of others, locating the diagnostics in `source`, which the diagram was parsed from. The
+/// diagram's references must have been resolved.
+pub fn check(source: &str, diagram: &Diagram<'_>) -> Vec<Diagnostic> {
+    let graph = Graph::new(diagram);
+    let metas = |node: usize| {
+        let span = graph.spans[node];
+        span.metas.unwrap_or((span.start, span.end))
+    };
+    let (mut coordinates, conflicts) = constraints::solve(&graph);
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for (constraint, conflict) in conflicts {
+        let message = format!("{} {}", constraint.source, conflict);
+        if diagnostics.iter().any(|diagnostic| diagnostic.message == message) {
+            continue;
+        }
+        diagnostics.push(Diagnostic::error(message).at(source, metas(constraint.node)));
+    }
+
+    // classifiers whose positions follow from relative positions can't be moved out of the way
+    let determined = constraints::fixed(&mut coordinates);
+    for (j, b) in determined.iter().enumerate() {
+        for (i, a) in determined[..j].iter().enumerate() {
+            let (Some((ax, ay)), Some((bx, by))) = (a, b) else { continue };
+            if (ax - bx).abs() >= 1.0 || (ay - by).abs() >= 1.0 {
+                continue;
+            }
+            let (node, other) = match (graph.fixed[i], graph.fixed[j]) {
+                (_, None) => (j, i),
+                (None, Some(_)) => (i, j),
+                (Some(_), Some(_)) => continue,
+            };
+            let message = format!("the relative position of `{}` places it on top of `{}`", graph.ids[node], graph.ids[other]);
+            diagnostics.push(Diagnostic::warning(message).at(source, metas(node)));
+        }
+    }
+    diagnostics
+}
+
+fn place_classifiers(
+    positions: &BTreeMap<String, Point>,
+    prefix: &str,
+    classifiers: &mut [Classifier<'_>],
+    packages: &mut [Package<'_>],
+) {
+    for classifier in classifiers {
+        let id = model::qualify(prefix, classifier.id.unwrap_or(&classifier.name));
+        if let Some(&(x, y)) = positions.get(&id) {
+            classifier.meta.retain(|_, meta| !meta.is_relative());
+            classifier.meta.entry("pos").or_insert(Meta::Position(x, y));
+        }
+    }
+    for package in packages {
+        let prefix = model::qualify(prefix, package.name);
+        place_classifiers(positions, &prefix, &mut package.classifiers, &mut package.packages);
+    }
+}
+
+fn collect_note_positions(occupied: &mut Vec<Point>, notes: &[Note<'_>], packages: &[Package<'_>]) {
+    occupied.extend(notes.iter().filter_map(|note| position(&note.meta)));
+    for package in packages {
+        collect_note_positions(occupied, &package.notes, &package.packages);
+    }
+}
+
+/// Places notes without a position next to the first element they are attached to, or below the
+/// diagram if they are free-standing.
+fn place_notes(
+    positions: &BTreeMap<String, Point>,
+    occupied: &mut Vec<Point>,
+    notes: &mut [Note<'_>],
+    packages: &mut [Package<'_>],
+) {
+    let is_free = |occupied: &[Point], (x, y): Point| {
+        occupied.iter().all(|&(ox, oy)| (ox - x).abs() >= 1.0 || (oy - y).abs() >= 1.0)
+    };
+
+    for note in notes.iter_mut().filter(|note| !note.meta.contains_key("pos")) {
+        // the default position, which relative positions may override
+        let anchor = note.anchors.first().and_then(|anchor| match anchor {
+            NoteAnchor::Classifier { id } => positions.get(id.as_ref()).copied(),
+            NoteAnchor::Member { classifier, .. } => positions.get(classifier.as_ref()).copied(),
+            NoteAnchor::Edge { a, b, .. } => {
+                let (ax, ay) = *positions.get(a.as_ref())?;
+                let (bx, by) = *positions.get(b.as_ref())?;
+                Some(((ax + bx) / 2.0, (ay + by) / 2.0))
+            }
+        });
+        let (x, y) = match anchor {
+            Some((x, y)) => {
+                const OFFSETS: [Point; 8] =
+                    [(1.0, 0.0), (-1.0, 0.0), (0.0, -1.0), (0.0, 1.0), (1.0, -1.0), (-1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+                OFFSETS
+                    .iter()
+                    .map(|(dx, dy)| (x + dx, y + dy))
+                    .find(|&point| is_free(occupied, point))
+                    .unwrap_or((x + 1.0, y))
+            }
+            None => {
+                let bottom = occupied.iter().map(|&(_, y)| y).fold(-1.0, f32::max) + 1.0;
+                let left = occupied.iter().map(|&(x, _)| x).fold(f32::INFINITY, f32::min);
+                let left = if left.is_finite() { left } else { 0.0 };
+                (0..)
+                    .map(|i| (left + i as f32, bottom))
+                    .find(|&point| is_free(occupied, point))
+                    .unwrap_or((left, bottom))
+            }
+        };
+        let (x, y) = note.meta.values().fold((x, y), |(x, y), meta| {
+            let position = |reference: &Cow<'_, str>| positions.get(reference.as_ref()).copied();
+            match meta {
+                Meta::RightOf(reference, distance) => position(reference).map(|(rx, ry)| (rx + distance, ry)),
+                Meta::LeftOf(reference, distance) => position(reference).map(|(rx, ry)| (rx - distance, ry)),
+                Meta::Above(reference, distance) => position(reference).map(|(rx, ry)| (rx, ry - distance)),
+                Meta::Below(reference, distance) => position(reference).map(|(rx, ry)| (rx, ry + distance)),
+                Meta::AlignX(references) => position(&references[0]).map(|(rx, _)| (rx, y)),
+                Meta::AlignY(references) => position(&references[0]).map(|(_, ry)| (x, ry)),
+                _ => None,
+            }
+            .unwrap_or((x, y))
+        });
+        occupied.push((x, y));
+        note.meta.retain(|_, meta| !meta.is_relative());
+        note.meta.insert("pos", Meta::Position(x, y));
+    }
+    for package in packages {
+        place_notes(positions, occupied, &mut package.notes, &mut package.packages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    pub fn test_layout(source: &str) -> BTreeMap<String, Point> {
+        test_layout_with(source, &Options::default())
+    }
+
+    pub fn test_layout_with(source: &str, options: &Options) -> BTreeMap<String, Point> {
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, options);
+        let graph = Graph::new(&diagram);
+        graph.ids.into_iter().zip(graph.fixed.into_iter().map(Option::unwrap)).collect()
+    }
+
+    pub fn test_layout_diagnostics(source: &str) -> Vec<String> {
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        check(source, &diagram).iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_graph() {
+        let mut diagram = parser::parse("class A\npackage p {\n  class B\n  B --|> A\n}\nA <.. p::B\nA -- C").unwrap();
+        diagram.resolve_references();
+        let graph = Graph::new(&diagram);
+        assert_eq!(graph.ids, ["A", "p::B"]);
+        assert_eq!(
+            graph.edges,
+            [
+                GraphEdge { from: 0, to: 1, hierarchy: false },
+                GraphEdge { from: 0, to: 1, hierarchy: true },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_place_notes() {
+        let mut diagram = parser::parse("#[pos(0, 0)]\nclass A\n#[pos(1, 0)]\nclass B\nnote on A \"a\"\nnote \"b\"").unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, &Options::default());
+        assert_eq!(diagram.notes[0].meta["pos"], Meta::Position(-1.0, 0.0));
+        assert_eq!(diagram.notes[1].meta["pos"], Meta::Position(-1.0, 1.0));
+    }
+
+    #[test]
+    fn test_route_edges() {
+        let source = "#[pos(0, 0)]\nclass A\n#[pos(1, 0)]\nclass B\n#[pos(2, 0)]\nclass C\nA -- C\n#[route(straight)]\nA -- C\nA -- B";
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, &Options::default());
+        assert!(matches!(diagram.edges[0].meta.get("via"), Some(Meta::Via(via)) if via.len() == 2));
+        assert_eq!(diagram.edges[1].meta.keys().collect::<Vec<_>>(), [&"route"]);
+        assert!(diagram.edges[2].meta.is_empty());
+    }
+
+    #[test]
+    fn test_grid_sizes() {
+        let positions = BTreeMap::from([("A".to_string(), (0.0, 0.0)), ("B".to_string(), (0.0, 1.0)), ("C".to_string(), (1.0, 1.0))]);
+        let measurements = Measurements {
+            sizes: BTreeMap::from([
+                ("A".to_string(), (60.0, 30.0)),
+                ("B".to_string(), (30.0, 10.0)),
+                ("C".to_string(), (20.0, 40.0)),
+                ("D".to_string(), (20.0, 20.0)),
+            ]),
+            spacing: 20.0,
+        };
+        let sizes = measurements.grid_sizes(&positions);
+        assert_eq!(sizes.len(), 3);
+        assert_eq!(sizes["A"], (0.75, 0.6));
+        assert_eq!(sizes["B"], (0.375, 1.0 / 6.0));
+        assert_eq!(sizes["C"], (0.5, 40.0 / 60.0));
+    }
+
+    #[test]
+    fn test_arrange() {
+        // with B measured as a large box, the edge from A to C has to go around it
+        let source = "#[pos(0, 0)]\nclass A\n#[pos(1, 0.5)]\nclass B\n#[pos(2, 0)]\nclass C\nA -- C";
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, &Options::default());
+        assert!(!diagram.edges[0].meta.contains_key("via"));
+
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        let measurements = Measurements {
+            sizes: BTreeMap::from([("B".to_string(), (100.0, 100.0))]),
+            spacing: 20.0,
+        };
+        arrange(&mut diagram, &Options::default(), &measurements);
+        assert!(diagram.edges[0].meta.contains_key("via"));
+    }
+
+    #[test]
+    fn test_cells() {
+        let measurements = Measurements {
+            sizes: BTreeMap::from([
+                ("A".to_string(), (300.0, 30.0)),
+                ("B".to_string(), (50.0, 30.0)),
+                ("C".to_string(), (40.0, 100.0)),
+            ]),
+            spacing: 20.0,
+        };
+        let cells = measurements.cells();
+        assert_eq!(cells["A"], (320.0 / 70.0, 1.0));
+        assert_eq!(cells["B"], (1.0, 1.0));
+        assert_eq!(cells["C"], (1.0, 120.0 / 50.0));
+    }
+
+    #[test]
+    fn test_arrange_wide_classifiers() {
+        let source = "class A\nclass B\nclass C\nA -- B\nB -- C";
+        let x = |diagram: &Diagram<'_>, i: usize| match diagram.classifiers[i].meta["pos"] {
+            Meta::Position(x, _) => x,
+            _ => unreachable!(),
+        };
+
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, &Options::default());
+        assert_eq!([x(&diagram, 0), x(&diagram, 1), x(&diagram, 2)], [0.0, 1.0, 2.0]);
+
+        // A is more than four cells wide, so its neighbours make room for it
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        let measurements = Measurements {
+            sizes: BTreeMap::from([
+                ("A".to_string(), (300.0, 30.0)),
+                ("B".to_string(), (50.0, 30.0)),
+                ("C".to_string(), (50.0, 30.0)),
+            ]),
+            spacing: 20.0,
+        };
+        arrange(&mut diagram, &Options::default(), &measurements);
+        assert_eq!([x(&diagram, 0), x(&diagram, 1), x(&diagram, 2)], [0.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_shape_edges() {
+        let source = "class A\nclass B\nA -- A\nA -- A\n#[bend(90deg)]\nB -- B\nB -- A\nA -- B\nA --> B\n#[bend(10deg)]\nA -- B";
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, &Options::default());
+        let meta = |i: usize, key: &str| diagram.edges[i].meta.get(key).cloned();
+
+        assert_eq!(meta(0, "bend"), Some(Meta::Bend(LOOP_BEND)));
+        assert_eq!(meta(0, "loop"), Some(Meta::Loop(LOOP_ANGLES[0])));
+        assert_eq!(meta(1, "loop"), Some(Meta::Loop(LOOP_ANGLES[1])));
+        assert_eq!(meta(2, "bend"), Some(Meta::Bend(PI / 2.0)));
+        assert_eq!(meta(2, "loop"), Some(Meta::Loop(LOOP_ANGLES[0])));
+
+        // `B -- A` goes the other way, so its bend is negated to fan out to the other side
+        assert_eq!(meta(3, "bend"), Some(Meta::Bend(FAN_BEND)));
+        assert_eq!(meta(4, "bend"), None);
+        assert_eq!(meta(5, "bend"), Some(Meta::Bend(FAN_BEND)));
+        assert_eq!(meta(6, "bend"), Some(Meta::Bend(10.0 * PI / 180.0)));
+    }
+
+    #[test]
+    fn test_resolve_anchors() {
+        let source = "#[pos(0, 0)]\nclass A\n#[pos(2, 0)]\nclass B\n#[via(A.south + (0, 0.5), (1, 2), B, C.east)]\nA -- B\n#[from(north), to(west)]\nA -- B";
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        layout(&mut diagram, &Options::default());
+        let Some(Meta::AnchoredVia(_, via)) = &diagram.edges[0].meta.get("via") else { panic!() };
+        assert_eq!(via, &[(0.0, 0.75), (1.0, 2.0), (2.0, 0.0)]);
+        assert_eq!(diagram.edges[1].meta["from"], Meta::From(Side::North, Some((0.0, -0.5))));
+        assert_eq!(diagram.edges[1].meta["to"], Meta::To(Side::West, Some((1.5, 0.0))));
+        assert!(!diagram.edges[1].meta.contains_key("via"));
+
+        // only the resolved points are passed on to Typst
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&diagram.edges[1], &mut bytes).unwrap();
+        let edge: ciborium::Value = ciborium::from_reader(&bytes[..]).unwrap();
+        let from = edge.as_map().unwrap().iter().find(|(k, _)| k.as_text() == Some("from")).unwrap();
+        let from: Vec<_> = from.1.as_array().unwrap().iter().map(|x| x.as_float().unwrap()).collect();
+        assert_eq!(from, [0.0, -0.5]);
+    }
+}
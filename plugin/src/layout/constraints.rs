@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{Axis, Constraint, Graph, Point};
+
+/// The coordinates along one axis that follow from fixed positions and relative position
+/// constraints. Nodes connected by constraints form a component; within a component, all
+/// coordinates are determined by any one of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinates {
+    /// the first node of each node's component
+    root: Vec<usize>,
+    /// each node's coordinate minus the coordinate of its root
+    offset: Vec<f32>,
+    /// the coordinate of each node, if its component contains a fixed position
+    value: Vec<Option<f32>>,
+}
+
+impl Coordinates {
+    fn new(n: usize) -> Self {
+        Self { root: (0..n).collect(), offset: vec![0.0; n], value: vec![None; n] }
+    }
+
+    /// Returns the root of a node's component, and the node's offset from it.
+    fn find(&mut self, node: usize) -> (usize, f32) {
+        let parent = self.root[node];
+        if parent == node {
+            return (node, 0.0);
+        }
+        let (root, offset) = self.find(parent);
+        self.root[node] = root;
+        self.offset[node] += offset;
+        (root, self.offset[node])
+    }
+
+    /// Records that `node`'s coordinate is `offset` more than `reference`'s, unless that contradicts
+    /// the constraints and fixed positions recorded so far.
+    fn constrain(&mut self, node: usize, reference: usize, offset: f32) -> Result<(), Conflict> {
+        let (node_root, node_offset) = self.find(node);
+        let (reference_root, reference_offset) = self.find(reference);
+        // the coordinate of `node_root` relative to `reference_root`
+        let root_offset = reference_offset + offset - node_offset;
+        if node_root == reference_root {
+            return if root_offset.abs() < EPSILON { Ok(()) } else { Err(Conflict::Cycle) };
+        }
+
+        let (node_value, reference_value) = (self.value[node_root], self.value[reference_root]);
+        let value = match (node_value, reference_value) {
+            (Some(node), Some(reference)) if (node - reference - root_offset).abs() >= EPSILON => {
+                return Err(Conflict::Fixed);
+            }
+            (node, reference) => reference.or(node.map(|node| node - root_offset)),
+        };
+        // keep the lower index as the root, so that the root is the component's first node
+        if node_root < reference_root {
+            self.root[reference_root] = node_root;
+            self.offset[reference_root] = -root_offset;
+            self.value[node_root] = value.map(|value| value + root_offset);
+        } else {
+            self.root[node_root] = reference_root;
+            self.offset[node_root] = root_offset;
+            self.value[reference_root] = value;
+        }
+        Ok(())
+    }
+
+    /// Returns the coordinate of a node if it is determined by a fixed position.
+    pub fn value(&mut self, node: usize) -> Option<f32> {
+        let (root, offset) = self.find(node);
+        self.value[root].map(|value| value + offset)
+    }
+
+    /// Moves nodes so that they satisfy the constraints: nodes in a component with a fixed
+    /// position get their determined coordinate, and the other components keep the coordinate of
+    /// their first node.
+    pub fn apply(&mut self, coordinates: &mut [f32]) {
+        for node in 0..coordinates.len() {
+            let (root, offset) = self.find(node);
+            coordinates[node] = match self.value[root] {
+                Some(value) => value + offset,
+                None => coordinates[root] + offset,
+            };
+        }
+    }
+}
+
+/// tolerance when comparing coordinates
+const EPSILON: f32 = 1e-3;
+
+/// Why a constraint can't be satisfied.
+pub enum Conflict {
+    /// the constraint contradicts a cycle of other constraints
+    Cycle,
+    /// the constraint would connect components whose fixed positions contradict it
+    Fixed,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cycle => write!(f, "forms a cycle with contradicting relative positions"),
+            Self::Fixed => write!(f, "contradicts fixed or other relative positions"),
+        }
+    }
+}
+
+/// Solves the graph's relative position constraints, starting from the fixed positions. The
+/// constraints are applied in order; constraints that contradict earlier ones are returned with
+/// the conflict, and left out.
+pub fn solve(graph: &Graph) -> ([Coordinates; 2], Vec<(&Constraint, Conflict)>) {
+    let n = graph.len();
+    let mut coordinates = [Coordinates::new(n), Coordinates::new(n)];
+    for (node, fixed) in graph.fixed.iter().enumerate() {
+        if let Some((x, y)) = *fixed {
+            coordinates[0].value[node] = Some(x);
+            coordinates[1].value[node] = Some(y);
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for constraint in &graph.constraints {
+        let coordinates = match constraint.axis {
+            Axis::X => &mut coordinates[0],
+            Axis::Y => &mut coordinates[1],
+        };
+        if let Err(conflict) = coordinates.constrain(constraint.node, constraint.reference, constraint.offset) {
+            conflicts.push((constraint, conflict));
+        }
+    }
+    (coordinates, conflicts)
+}
+
+/// Moves nodes that overlap nodes before them out of the way, together with the nodes positioned
+/// relative to them, so that the relative positions still hold. Each node takes up the number of
+/// grid cells given in `cells`, centered on its position. Such a group is moved to the right, or
+/// down if its x coordinates are determined by fixed positions; groups with determined x and y
+/// coordinates stay where they are, and overlaps among them are reported by [super::check].
+pub fn separate(coordinates: &mut [Coordinates; 2], xs: &mut [f32], ys: &mut [f32], cells: &[(f32, f32)]) {
+    let [x_coordinates, y_coordinates] = coordinates;
+    let n = xs.len();
+
+    // nodes connected by constraints along either axis form a group
+    fn find(group: &mut [usize], mut node: usize) -> usize {
+        while group[node] != node {
+            group[node] = group[group[node]];
+            node = group[node];
+        }
+        node
+    }
+    let mut group: Vec<usize> = (0..n).collect();
+    for node in 0..n {
+        for (root, _) in [x_coordinates.find(node), y_coordinates.find(node)] {
+            let (a, b) = (find(&mut group, node), find(&mut group, root));
+            group[a.max(b)] = a.min(b);
+        }
+    }
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for node in 0..n {
+        groups.entry(find(&mut group, node)).or_default().push(node);
+    }
+
+    let mut determined = |node: usize| (x_coordinates.value(node).is_some(), y_coordinates.value(node).is_some());
+    let determined: Vec<_> = (0..n).map(&mut determined).collect();
+    let mut occupied: Vec<(Point, (f32, f32))> =
+        (0..n).filter(|&i| determined[i] == (true, true)).map(|i| ((xs[i], ys[i]), cells[i])).collect();
+    for nodes in groups.values() {
+        if nodes.iter().any(|&i| determined[i] == (true, true)) {
+            continue;
+        }
+        let step = if nodes.iter().all(|&i| !determined[i].0) {
+            (1.0, 0.0)
+        } else if nodes.iter().all(|&i| !determined[i].1) {
+            (0.0, 1.0)
+        } else {
+            (0.0, 0.0)
+        };
+        let overlaps = |k: f32| {
+            nodes.iter().any(|&i| {
+                let ((x, y), (w, h)) = ((xs[i] + k * step.0, ys[i] + k * step.1), cells[i]);
+                occupied.iter().any(|&((ox, oy), (ow, oh))| {
+                    (ox - x).abs() < (ow + w) / 2.0 - EPSILON && (oy - y).abs() < (oh + h) / 2.0 - EPSILON
+                })
+            })
+        };
+        // every occupied node blocks at most as many steps as the two nodes take up cells, plus one
+        let blocked = |i: usize, (w, h): (f32, f32)| (w + cells[i].0).max(h + cells[i].1).ceil() as usize + 1;
+        let limit = if step == (0.0, 0.0) {
+            0
+        } else {
+            nodes.iter().map(|&i| occupied.iter().map(|&(_, cells)| blocked(i, cells)).sum::<usize>()).sum()
+        };
+        let k = (0..=limit).map(|k| k as f32).find(|&k| !overlaps(k)).unwrap_or(0.0);
+        for &i in nodes {
+            xs[i] += k * step.0;
+            ys[i] += k * step.1;
+            occupied.push(((xs[i], ys[i]), cells[i]));
+        }
+    }
+}
+
+/// Returns the positions of all nodes whose coordinates are both determined.
+pub fn fixed(coordinates: &mut [Coordinates; 2]) -> Vec<Option<Point>> {
+    let [xs, ys] = coordinates;
+    (0..xs.root.len()).map(|node| Some((xs.value(node)?, ys.value(node)?))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{test_layout, test_layout_diagnostics};
+
+    #[test]
+    fn test_relative_positions() {
+        let positions = test_layout(
+            "#[pos(2, 3)]\nclass A\n#[right-of(A)]\nclass B\n#[below(B, 2)]\nclass C\n#[align-x(A), align-y(C)]\nclass D",
+        );
+        assert_eq!(positions["A"], (2.0, 3.0));
+        assert_eq!(positions["B"], (3.0, 3.0));
+        assert_eq!(positions["C"], (3.0, 5.0));
+        assert_eq!(positions["D"], (2.0, 5.0));
+    }
+
+    #[test]
+    fn test_unanchored_relative_positions() {
+        let positions = test_layout("class A\n#[left-of(A, 2)]\nclass B\n#[above(B)]\nclass C\nC --|> A");
+        let (a, b, c) = (positions["A"], positions["B"], positions["C"]);
+        assert_eq!((b.0 - a.0, b.1 - a.1), (-2.0, 0.0));
+        assert_eq!((c.0 - b.0, c.1 - b.1), (0.0, -1.0));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        assert_eq!(test_layout_diagnostics("class A\n#[right-of(A)]\nclass B\n#[align-x(A)]\nclass C\n#[align-x(B, C)]\nclass D"), [
+            "error: `align-x(B, C)` of `D` forms a cycle with contradicting relative positions",
+        ]);
+        assert_eq!(test_layout_diagnostics("#[right-of(B)]\nclass A\n#[right-of(A)]\nclass B"), [
+            "error: `right-of(A)` of `B` forms a cycle with contradicting relative positions",
+        ]);
+        assert_eq!(test_layout_diagnostics("#[pos(0, 0)]\nclass A\n#[pos(0, 1), right-of(A)]\nclass B"), [
+            "error: `right-of(A)` of `B` contradicts fixed or other relative positions",
+        ]);
+        assert!(test_layout_diagnostics("#[align-x(B)]\nclass A\n#[align-x(A)]\nclass B").is_empty());
+    }
+
+    #[test]
+    fn test_overlaps() {
+        // fixed positions leave no room, so the overlap is reported
+        let source = "#[pos(0, 0)]\nclass A\n#[pos(1, 0)]\nclass C\n#[right-of(A)]\nclass B";
+        assert_eq!(test_layout_diagnostics(source), ["warning: the relative position of `B` places it on top of `C`"]);
+        assert_eq!(test_layout(source)["B"], (1.0, 0.0));
+
+        // automatically placed classifiers are moved out of the way, and groups of relatively
+        // positioned classifiers are moved as a whole
+        for source in [
+            "class A\nclass C\nclass D\nA -- C\nC -- D\n#[right-of(A)]\nclass B",
+            "class C\nclass D\nC -- D\nclass A\n#[right-of(A)]\nclass B\n#[below(B)]\nclass E\nD -- A",
+        ] {
+            assert!(test_layout_diagnostics(source).is_empty());
+            let positions = test_layout(source);
+            let (a, b) = (positions["A"], positions["B"]);
+            assert_eq!((b.0 - a.0, b.1 - a.1), (1.0, 0.0), "{:?}", positions);
+            let positions: Vec<_> = positions.values().collect();
+            for (i, p) in positions.iter().enumerate() {
+                for q in &positions[..i] {
+                    assert!((p.0 - q.0).abs() >= 1.0 || (p.1 - q.1).abs() >= 1.0, "{:?}", positions);
+                }
+            }
+        }
+    }
+}
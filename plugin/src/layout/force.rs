@@ -0,0 +1,175 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::f32::consts::PI;
+
+use super::{Graph, Point};
+
+/// the maximum number of stress majorization iterations
+const ITERATIONS: usize = 300;
+/// iterations stop once no node moves farther than this
+const TOLERANCE: f32 = 1e-4;
+
+/// Computes a force-directed layout using stress majorization: the layout tries to make the
+/// euclidean distance between any two nodes proportional to the length of the shortest path
+/// between them. Edge kinds and directions are ignored. Nodes with fixed positions keep them, and
+/// all other nodes are snapped to distinct grid cells. The result only depends on the graph, so it
+/// is the same every time.
+pub fn layout(graph: &Graph) -> Vec<Point> {
+    let n = graph.len();
+    let distances = distances(graph);
+
+    // start on a circle, in source order, unless the position is fixed
+    let radius = n as f32 / (2.0 * PI);
+    let mut positions: Vec<Point> = (0..n)
+        .map(|i| {
+            graph.fixed[i].unwrap_or_else(|| {
+                let angle = 2.0 * PI * i as f32 / n as f32;
+                (radius * angle.cos(), radius * angle.sin())
+            })
+        })
+        .collect();
+
+    for _ in 0..ITERATIONS {
+        let mut movement: f32 = 0.0;
+        for i in (0..n).filter(|&i| graph.fixed[i].is_none()) {
+            let (xi, yi) = positions[i];
+            let (mut x, mut y, mut weights) = (0.0, 0.0, 0.0);
+            for j in (0..n).filter(|&j| j != i) {
+                let (xj, yj) = positions[j];
+                let d = distances[i][j];
+                let w = 1.0 / (d * d);
+                let (dx, dy) = (xi - xj, yi - yj);
+                let norm = (dx * dx + dy * dy).sqrt();
+                // nodes at the same spot are pushed apart in a fixed direction
+                let (ux, uy) = if norm > f32::EPSILON { (dx / norm, dy / norm) } else { (1.0, 0.0) };
+                x += w * (xj + d * ux);
+                y += w * (yj + d * uy);
+                weights += w;
+            }
+            if weights > 0.0 {
+                let (x, y) = (x / weights, y / weights);
+                movement = movement.max((x - xi).abs()).max((y - yi).abs());
+                positions[i] = (x, y);
+            }
+        }
+        if movement < TOLERANCE {
+            break;
+        }
+    }
+
+    // without fixed nodes, the layout may be anywhere; move it to the origin
+    if graph.fixed.iter().all(Option::is_none) {
+        let left = positions.iter().map(|&(x, _)| x).fold(f32::INFINITY, f32::min);
+        let top = positions.iter().map(|&(_, y)| y).fold(f32::INFINITY, f32::min);
+        if left.is_finite() && top.is_finite() {
+            for (x, y) in &mut positions {
+                *x -= left;
+                *y -= top;
+            }
+        }
+    }
+
+    snap(graph, &mut positions);
+    positions
+}
+
+/// Returns the lengths of the shortest paths between all pairs of nodes. Nodes that are not
+/// connected are treated as one step farther apart than the farthest connected ones.
+fn distances(graph: &Graph) -> Vec<Vec<f32>> {
+    let n = graph.len();
+    let mut neighbors = vec![Vec::new(); n];
+    for edge in graph.edges.iter().filter(|edge| edge.from != edge.to) {
+        neighbors[edge.from].push(edge.to);
+        neighbors[edge.to].push(edge.from);
+    }
+
+    let mut distances = vec![vec![None; n]; n];
+    for (start, distances) in distances.iter_mut().enumerate() {
+        distances[start] = Some(0);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            let distance = distances[node].unwrap_or(0);
+            for &neighbor in &neighbors[node] {
+                if distances[neighbor].is_none() {
+                    distances[neighbor] = Some(distance + 1);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    let unconnected = distances.iter().flatten().flatten().copied().max().unwrap_or(0) + 1;
+    distances
+        .into_iter()
+        .map(|row| row.into_iter().map(|d| d.unwrap_or(unconnected) as f32).collect())
+        .collect()
+}
+
+/// Moves the nodes that are not fixed to the nearest free grid cell, one at a time in source order.
+fn snap(graph: &Graph, positions: &mut [Point]) {
+    let cell = |(x, y): Point| (x.round() as i64, y.round() as i64);
+    let mut occupied: BTreeSet<_> = graph.fixed.iter().flatten().map(|&point| cell(point)).collect();
+    for (i, position) in positions.iter_mut().enumerate() {
+        if graph.fixed[i].is_some() {
+            continue;
+        }
+        let (x, y) = cell(*position);
+        // search rings of increasing size around the preferred cell; within a ring, prefer the
+        // cell closest to the unsnapped position
+        let free = (0..)
+            .find_map(|ring: i64| {
+                let candidates = (-ring..=ring)
+                    .flat_map(|dx| (-ring..=ring).map(move |dy| (x + dx, y + dy)))
+                    .filter(|&(cx, cy)| (cx - x).abs() == ring || (cy - y).abs() == ring)
+                    .filter(|candidate| !occupied.contains(candidate));
+                candidates.min_by(|&(ax, ay), &(bx, by)| {
+                    let distance = |cx: i64, cy: i64| (cx as f32 - position.0).powi(2) + (cy as f32 - position.1).powi(2);
+                    distance(ax, ay).total_cmp(&distance(bx, by))
+                })
+            })
+            .unwrap_or((x, y));
+        occupied.insert(free);
+        *position = (free.0 as f32, free.1 as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::super::tests::test_layout_with;
+    use super::super::{Algorithm, Options};
+
+    fn options() -> Options {
+        Options { algorithm: Algorithm::Force, ..Default::default() }
+    }
+
+    #[test]
+    fn test_distinct_cells() {
+        let positions = test_layout_with(
+            "class A\nclass B\nclass C\nclass D\nclass E\nA -- B\nB -- C\nC -- D\nD -- A\nA -- E",
+            &options(),
+        );
+        let cells: BTreeSet<_> = positions.values().map(|&(x, y)| (x as i64, y as i64)).collect();
+        assert_eq!(cells.len(), 5);
+        assert!(positions.values().all(|&(x, y)| x.fract() == 0.0 && y.fract() == 0.0));
+    }
+
+    #[test]
+    fn test_neighbors_are_close() {
+        let positions = test_layout_with("class A\nclass B\nclass C\nclass D\nA -- B\nB -- C\nC -- D", &options());
+        let distance = |a: &str, b: &str| {
+            let ((ax, ay), (bx, by)) = (positions[a], positions[b]);
+            (ax - bx).abs().max((ay - by).abs())
+        };
+        assert!(distance("A", "B") <= 1.0 && distance("B", "C") <= 1.0 && distance("C", "D") <= 1.0);
+        assert!(distance("A", "D") >= 2.0, "{:?}", positions);
+    }
+
+    #[test]
+    fn test_fixed_and_deterministic() {
+        let source = "#[pos(5, 5)]\nclass A\nclass B\nclass C\nA -- B\nA -- C\nB -- C";
+        let positions = test_layout_with(source, &options());
+        assert_eq!(positions["A"], (5.0, 5.0));
+        assert_eq!(positions, test_layout_with(source, &options()));
+    }
+}
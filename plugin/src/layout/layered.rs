@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+
+use super::{Graph, Point};
+
+/// the number of barycenter sweeps used to reduce crossings
+const SWEEPS: usize = 12;
+
+/// A node in the layered graph: either a classifier or a dummy node that is part of an edge
+/// spanning several layers.
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    fixed: Option<Point>,
+    layer: i64,
+}
+
+/// Computes a Sugiyama-style layered layout: classifiers are assigned to layers so that supertypes
+/// are above their subtypes, the order within each layer is chosen to reduce edge crossings, and
+/// finally each classifier gets an x coordinate close to its neighbors. Nodes with fixed positions
+/// keep them.
+pub fn layout(graph: &Graph) -> Vec<Point> {
+    let rows = fixed_rows(graph);
+    let ranks = ranks(graph, &rows);
+
+    let mut nodes: Vec<_> = (0..graph.len()).map(|i| Node { fixed: graph.fixed[i], layer: ranks[i] }).collect();
+    let mut neighbors = vec![Vec::new(); nodes.len()];
+    for edge in &graph.edges {
+        let (mut from, to) = (edge.from, edge.to);
+        let (start, end) = (nodes[from].layer, nodes[to].layer);
+        if start == end {
+            continue;
+        }
+        // replace long edges by chains of dummy nodes, one per layer in between
+        let step = if start < end { 1 } else { -1 };
+        let mut layer = start + step;
+        while layer != end {
+            nodes.push(Node { fixed: None, layer });
+            neighbors.push(Vec::new());
+            let dummy = nodes.len() - 1;
+            neighbors[from].push(dummy);
+            neighbors[dummy].push(from);
+            from = dummy;
+            layer += step;
+        }
+        neighbors[from].push(to);
+        neighbors[to].push(from);
+    }
+
+    let mut layers: BTreeMap<i64, Vec<usize>> = BTreeMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        layers.entry(node.layer).or_default().push(i);
+    }
+    let mut layers: Vec<Vec<usize>> = layers.into_values().collect();
+    for layer in &mut layers {
+        // start with fixed nodes in their order, followed by the others in source order
+        layer.sort_by(|&a, &b| {
+            let key = |i: usize| nodes[i].fixed.map(|(x, _)| x);
+            match (key(a), key(b)) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => a.cmp(&b),
+            }
+        });
+    }
+
+    order(&mut layers, &nodes, &neighbors);
+    let xs = coordinates(&layers, &nodes, &neighbors);
+
+    (0..graph.len())
+        .map(|i| nodes[i].fixed.unwrap_or((xs[i], row(&rows, nodes[i].layer))))
+        .collect()
+}
+
+/// Returns the distinct rows of the fixed nodes, in order. Layers are numbered by the ranks of
+/// these rows rather than the rows themselves, so that fixed nodes far apart don't need long
+/// chains of dummy nodes in between.
+fn fixed_rows(graph: &Graph) -> Vec<i64> {
+    let mut rows: Vec<_> = graph.fixed.iter().flatten().map(|&(_, y)| y.round() as i64).collect();
+    rows.sort_unstable();
+    rows.dedup();
+    rows
+}
+
+/// Returns the y coordinate of a layer: the row of its fixed nodes, or for layers above and below
+/// all fixed nodes, one apart from the closest fixed row.
+fn row(rows: &[i64], layer: i64) -> f32 {
+    let (Some(&first), Some(&last)) = (rows.first(), rows.last()) else {
+        return layer as f32;
+    };
+    let max = rows.len() as i64 - 1;
+    let row = match usize::try_from(layer) {
+        Err(_) => first + layer,
+        Ok(index) => rows.get(index).copied().unwrap_or(last + layer - max),
+    };
+    row as f32
+}
+
+/// Assigns each node to a layer. Fixed nodes are in the layer of the row closest to their y
+/// coordinate; see [fixed_rows]. Other nodes are placed one layer below their lowest supertype; roots are placed directly above their
+/// highest subtype, and nodes without any hierarchy edges share a layer with a node they are
+/// associated with.
+fn ranks(graph: &Graph, rows: &[i64]) -> Vec<i64> {
+    let n = graph.len();
+    let hierarchy = acyclic_hierarchy(graph);
+    let mut parents = vec![Vec::new(); n];
+    let mut children = vec![Vec::new(); n];
+    for &(from, to) in &hierarchy {
+        parents[to].push(from);
+        children[from].push(to);
+    }
+    let fixed = |i: usize| graph.fixed[i].map(|(_, y)| rows.partition_point(|&row| row < y.round() as i64) as i64);
+
+    // topological order, parents first
+    let mut order = Vec::with_capacity(n);
+    let mut pending: Vec<_> = parents.iter().map(Vec::len).collect();
+    let mut queue: Vec<_> = (0..n).filter(|&i| pending[i] == 0).rev().collect();
+    while let Some(i) = queue.pop() {
+        order.push(i);
+        for &child in children[i].iter().rev() {
+            pending[child] -= 1;
+            if pending[child] == 0 {
+                queue.push(child);
+            }
+        }
+    }
+
+    let mut ranks = vec![0; n];
+    for &i in &order {
+        ranks[i] = fixed(i).unwrap_or_else(|| parents[i].iter().map(|&parent| ranks[parent] + 1).max().unwrap_or(0));
+    }
+    for &i in order.iter().rev() {
+        if fixed(i).is_none() && parents[i].is_empty() {
+            if let Some(rank) = children[i].iter().map(|&child| ranks[child] - 1).min() {
+                ranks[i] = rank;
+            }
+        }
+    }
+
+    // nodes only connected by other edges are placed next to their neighbors
+    let mut placed: Vec<_> = (0..n).map(|i| fixed(i).is_some() || !parents[i].is_empty() || !children[i].is_empty()).collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for edge in graph.edges.iter().filter(|edge| !edge.hierarchy) {
+            for (a, b) in [(edge.from, edge.to), (edge.to, edge.from)] {
+                if placed[a] && !placed[b] {
+                    ranks[b] = ranks[a];
+                    placed[b] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+    ranks
+}
+
+/// Returns the hierarchy edges, leaving out self loops and the edges that would close a cycle.
+fn acyclic_hierarchy(graph: &Graph) -> Vec<(usize, usize)> {
+    let n = graph.len();
+    let mut successors = vec![Vec::new(); n];
+    for edge in graph.edges.iter().filter(|edge| edge.hierarchy && edge.from != edge.to) {
+        successors[edge.from].push(edge.to);
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Active,
+        Done,
+    }
+
+    let mut state = vec![State::New; n];
+    let mut result = Vec::new();
+    for root in 0..n {
+        if state[root] != State::New {
+            continue;
+        }
+        // iterative depth-first search; edges to active nodes are back edges
+        state[root] = State::Active;
+        let mut stack = vec![(root, 0)];
+        while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+            if let Some(&successor) = successors[node].get(*next) {
+                *next += 1;
+                match state[successor] {
+                    State::Active => {}
+                    State::Done => result.push((node, successor)),
+                    State::New => {
+                        result.push((node, successor));
+                        state[successor] = State::Active;
+                        stack.push((successor, 0));
+                    }
+                }
+            } else {
+                state[node] = State::Done;
+                stack.pop();
+            }
+        }
+    }
+    result
+}
+
+/// Reorders the nodes within their layers to reduce crossings, using the barycenter heuristic.
+fn order(layers: &mut [Vec<usize>], nodes: &[Node], neighbors: &[Vec<usize>]) {
+    let mut best = layers.to_vec();
+    let mut best_crossings = crossings(layers, neighbors, nodes.len());
+    for sweep in 0..SWEEPS {
+        let mut positions = vec![0.0; nodes.len()];
+        let indices: Vec<usize> = if sweep % 2 == 0 {
+            (1..layers.len()).collect()
+        } else {
+            (0..layers.len().saturating_sub(1)).rev().collect()
+        };
+        for layer in layers.iter() {
+            for (position, &node) in layer.iter().enumerate() {
+                positions[node] = position as f64;
+            }
+        }
+        for i in indices {
+            let adjacent = if sweep % 2 == 0 { i - 1 } else { i + 1 };
+            let adjacent_layer = nodes[layers[adjacent][0]].layer;
+            let keys: BTreeMap<usize, f64> = layers[i]
+                .iter()
+                .map(|&node| {
+                    let adjacent = neighbors[node].iter().filter(|&&neighbor| nodes[neighbor].layer == adjacent_layer);
+                    let (sum, count) = adjacent.fold((0.0, 0), |(sum, count), &neighbor| (sum + positions[neighbor], count + 1));
+                    let key = if count > 0 { sum / count as f64 } else { positions[node] };
+                    (node, key)
+                })
+                .collect();
+            layers[i].sort_by(|a, b| keys[a].total_cmp(&keys[b]));
+            for (position, &node) in layers[i].iter().enumerate() {
+                positions[node] = position as f64;
+            }
+        }
+        let crossings = crossings(layers, neighbors, nodes.len());
+        if crossings < best_crossings {
+            best = layers.to_vec();
+            best_crossings = crossings;
+        }
+    }
+    layers.clone_from_slice(&best);
+
+    // fixed nodes must stay in the order of their x coordinates
+    for layer in layers.iter_mut() {
+        let slots: Vec<_> = (0..layer.len()).filter(|&i| nodes[layer[i]].fixed.is_some()).collect();
+        let mut fixed: Vec<_> = slots.iter().map(|&i| layer[i]).collect();
+        fixed.sort_by(|&a, &b| {
+            let x = |i: usize| nodes[i].fixed.map_or(0.0, |(x, _)| x);
+            x(a).total_cmp(&x(b))
+        });
+        for (slot, node) in slots.into_iter().zip(fixed) {
+            layer[slot] = node;
+        }
+    }
+}
+
+/// Counts the crossings between edges connecting adjacent layers.
+fn crossings(layers: &[Vec<usize>], neighbors: &[Vec<usize>], n: usize) -> usize {
+    let mut positions = vec![usize::MAX; n];
+    let mut result = 0;
+    for pair in layers.windows(2) {
+        for (position, &node) in pair[1].iter().enumerate() {
+            positions[node] = position;
+        }
+        let mut edges = Vec::new();
+        for (position, &node) in pair[0].iter().enumerate() {
+            for &neighbor in &neighbors[node] {
+                if pair[1].contains(&neighbor) {
+                    edges.push((position, positions[neighbor]));
+                }
+            }
+        }
+        for (i, &(a1, b1)) in edges.iter().enumerate() {
+            for &(a2, b2) in &edges[i + 1..] {
+                if (a1 < a2 && b1 > b2) || (a1 > a2 && b1 < b2) {
+                    result += 1;
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Assigns x coordinates to all nodes, at least one apart within a layer and in layer order. Each
+/// node is first placed below its neighbors in the layer above, then centered above its neighbors
+/// in the layer below.
+fn coordinates(layers: &[Vec<usize>], nodes: &[Node], neighbors: &[Vec<usize>]) -> Vec<f32> {
+    let mut xs = vec![0.0; nodes.len()];
+    let desired = |xs: &[f32], node: usize, layer: i64| {
+        let adjacent = neighbors[node].iter().filter(|&&neighbor| nodes[neighbor].layer == layer);
+        let (sum, count) = adjacent.fold((0.0, 0), |(sum, count), &neighbor| (sum + xs[neighbor], count + 1));
+        (count > 0).then(|| sum / count as f32)
+    };
+
+    for (i, layer) in layers.iter().enumerate() {
+        let above = i.checked_sub(1).map(|i| nodes[layers[i][0]].layer);
+        let targets: Vec<_> = layer
+            .iter()
+            .enumerate()
+            .map(|(position, &node)| above.and_then(|above| desired(&xs, node, above)).unwrap_or(position as f32))
+            .collect();
+        place_layer(&mut xs, layer, nodes, &targets);
+    }
+    for i in (0..layers.len().saturating_sub(1)).rev() {
+        let below = nodes[layers[i + 1][0]].layer;
+        let targets: Vec<_> = layers[i].iter().map(|&node| desired(&xs, node, below).unwrap_or(xs[node])).collect();
+        place_layer(&mut xs, &layers[i], nodes, &targets);
+    }
+
+    // snap to half grid cells; as rounding is monotonic, nodes stay at least one apart
+    for x in &mut xs {
+        *x = (*x * 2.0).round() / 2.0;
+    }
+    xs
+}
+
+/// Places the nodes of a layer as close as possible to their targets while keeping them in order
+/// and at least one apart. Fixed nodes are not moved.
+fn place_layer(xs: &mut [f32], layer: &[usize], nodes: &[Node], targets: &[f32]) {
+    let mut previous: Option<f32> = None;
+    for (&node, &target) in layer.iter().zip(targets) {
+        xs[node] = match nodes[node].fixed {
+            Some((x, _)) => x,
+            None => previous.map_or(target, |previous| target.max(previous + 1.0)),
+        };
+        previous = Some(xs[node]);
+    }
+    let mut next: Option<f32> = None;
+    for &node in layer.iter().rev() {
+        if nodes[node].fixed.is_none() {
+            if let Some(next) = next {
+                xs[node] = xs[node].min(next - 1.0);
+            }
+        }
+        next = Some(xs[node]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::test_layout;
+
+    #[test]
+    fn test_hierarchy() {
+        let positions = test_layout("class A\nclass B\nclass C\nB --|> A\nA <|-- C\ninterface I\nC ..|> I");
+        let (a, b, c, i) = (positions["A"], positions["B"], positions["C"], positions["I"]);
+        assert!(a.1 < b.1 && a.1 < c.1 && i.1 < c.1, "{:?}", positions);
+        assert_eq!(b.1, c.1);
+        assert!(b.0 != c.0);
+    }
+
+    #[test]
+    fn test_fixed_positions() {
+        let positions = test_layout("#[pos(3, 5)]\nclass A\nclass B\nB --|> A\nclass C\nC -- B");
+        assert_eq!(positions["A"], (3.0, 5.0));
+        assert_eq!(positions["B"], (3.0, 6.0));
+        assert_eq!(positions["C"], (4.0, 6.0));
+    }
+
+    #[test]
+    fn test_distant_fixed_positions() {
+        // layers between fixed rows are not materialized, so this doesn't create millions of nodes
+        let positions = test_layout("#[pos(0, 0)]\nclass A\n#[pos(0, 3000000)]\nclass B\nA -- B\nclass C\nC --|> B\nclass D\nA --|> D");
+        assert_eq!(positions["A"], (0.0, 0.0));
+        assert_eq!(positions["B"], (0.0, 3000000.0));
+        assert_eq!(positions["C"].1, 3000001.0);
+        assert_eq!(positions["D"].1, -1.0);
+    }
+
+    #[test]
+    fn test_crossings() {
+        // without reordering, the edges A-D and B-C would cross
+        let positions = test_layout("class A\nclass B\nclass C\nclass D\nC --|> B\nD --|> A");
+        assert!((positions["A"].0 < positions["B"].0) == (positions["D"].0 < positions["C"].0), "{:?}", positions);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let positions = test_layout("class A\nclass B\nA --|> B\nB --|> A\nA --|> A");
+        assert_ne!(positions["A"], positions["B"]);
+    }
+}
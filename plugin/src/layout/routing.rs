@@ -0,0 +1,230 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, BTreeMap};
+
+use crate::model::Routing;
+
+use super::Point;
+
+/// the distance kept between routes and the nodes they pass
+const MARGIN: f32 = 0.1;
+/// the cost of a bend, relative to the cost of a route's length
+const BEND_PENALTY: f32 = 0.5;
+
+/// The area covered by a node, in grid coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub center: Point,
+    /// half the width and height
+    pub half: (f32, f32),
+}
+
+impl Rect {
+    fn inflate(self, margin: f32) -> Self {
+        let (w, h) = self.half;
+        Self { center: self.center, half: (w + margin, h + margin) }
+    }
+
+    /// whether the point is strictly inside
+    fn contains(&self, (x, y): Point) -> bool {
+        let ((cx, cy), (w, h)) = (self.center, self.half);
+        (x - cx).abs() < w && (y - cy).abs() < h
+    }
+
+    /// whether the segment passes through the inside
+    fn intersects(&self, (x1, y1): Point, (x2, y2): Point) -> bool {
+        let ((cx, cy), (w, h)) = (self.center, self.half);
+        // clip the segment's parameter range against both slabs (Liang-Barsky)
+        let (mut t0, mut t1) = (0.0f32, 1.0f32);
+        for (start, delta, low, high) in [(x1, x2 - x1, cx - w, cx + w), (y1, y2 - y1, cy - h, cy + h)] {
+            if delta.abs() < f32::EPSILON {
+                if start <= low || start >= high {
+                    return false;
+                }
+            } else {
+                let (a, b) = ((low - start) / delta, (high - start) / delta);
+                t0 = t0.max(a.min(b));
+                t1 = t1.min(a.max(b));
+            }
+        }
+        t1 - t0 > f32::EPSILON
+    }
+}
+
+/// Computes the intermediate points of a route from `source` to `target` that avoids
+/// `obstacles`, or `None` if the edge should be drawn straight. An orthogonal route consists of
+/// horizontal and vertical segments; with [Routing::Auto], edges are only routed orthogonally if
+/// a straight line would cross an obstacle.
+pub fn route(source: &Rect, target: &Rect, obstacles: &[Rect], routing: Routing) -> Option<Vec<Point>> {
+    let obstacles: Vec<_> = obstacles.iter().map(|obstacle| obstacle.inflate(MARGIN)).collect();
+    match routing {
+        Routing::Straight => return None,
+        Routing::Auto if !obstacles.iter().any(|obstacle| obstacle.intersects(source.center, target.center)) => {
+            return None;
+        }
+        _ => {}
+    }
+    let points = orthogonal(source, target, &obstacles)?;
+    let via = points[1..points.len() - 1].to_vec();
+    (!via.is_empty()).then_some(via)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Heading {
+    Horizontal,
+    Vertical,
+}
+
+/// a grid point by the indices of its lines, and the heading it was reached with
+type State = ((usize, usize), Option<Heading>);
+
+/// Finds the shortest orthogonal route, counting bends as extra length, on a grid of lines
+/// through the endpoints' centers, along the obstacles' borders and between them. Returns the
+/// route's corners, including the centers of source and target.
+fn orthogonal(source: &Rect, target: &Rect, obstacles: &[Rect]) -> Option<Vec<Point>> {
+    let (source_rect, target_rect) = (source.inflate(MARGIN), target.inflate(MARGIN));
+    let lines = |coordinate: fn(Point) -> f32, half: fn((f32, f32)) -> f32| {
+        let mut lines = vec![coordinate(source.center), coordinate(target.center)];
+        for rect in obstacles.iter().chain([&source_rect, &target_rect]) {
+            lines.push(coordinate(rect.center) - half(rect.half));
+            lines.push(coordinate(rect.center) + half(rect.half));
+        }
+        lines.sort_by(f32::total_cmp);
+        lines.dedup_by(|a, b| (*a - *b).abs() < 1e-4);
+        let midpoints: Vec<_> = lines.windows(2).map(|pair| (pair[0] + pair[1]) / 2.0).collect();
+        lines.extend(midpoints);
+        let (first, last) = (lines[0], lines[lines.len() - 1]);
+        // leave room to go around everything
+        lines.extend([first - 0.5, last + 0.5]);
+        lines.sort_by(f32::total_cmp);
+        lines
+    };
+    let xs = lines(|(x, _)| x, |(w, _)| w);
+    let ys = lines(|(_, y)| y, |(_, h)| h);
+    let index = |lines: &[f32], value: f32| lines.iter().position(|&line| (line - value).abs() < 1e-4);
+    let start = (index(&xs, source.center.0)?, index(&ys, source.center.1)?);
+    let end = (index(&xs, target.center.0)?, index(&ys, target.center.1)?);
+
+    let point = |(i, j): (usize, usize)| (xs[i], ys[j]);
+    // points inside the source or target are only allowed at their centers
+    let allowed = |node: (usize, usize)| {
+        let p = point(node);
+        node == start || node == end || !(source.contains(p) || target.contains(p))
+    };
+    let blocked = |a: Point, b: Point| obstacles.iter().any(|obstacle| obstacle.intersects(a, b) || obstacle.contains(b));
+
+    // Dijkstra over (grid point, heading); costs are scaled to integers to be totally ordered
+    let scale = |cost: f32| (cost * 1000.0).round() as u64;
+    let mut best: BTreeMap<State, u64> = BTreeMap::new();
+    let mut previous: BTreeMap<State, State> = BTreeMap::new();
+    let mut queue = BinaryHeap::new();
+    best.insert((start, None), 0);
+    queue.push(Reverse((0, start, None)));
+    let mut found = None;
+    while let Some(Reverse((cost, node, heading))) = queue.pop() {
+        if best.get(&(node, heading)).is_some_and(|&best| best < cost) {
+            continue;
+        }
+        if node == end {
+            found = Some((node, heading));
+            break;
+        }
+        let (i, j) = node;
+        let steps: [(isize, isize, Heading); 4] =
+            [(1, 0, Heading::Horizontal), (-1, 0, Heading::Horizontal), (0, 1, Heading::Vertical), (0, -1, Heading::Vertical)];
+        for (di, dj, step_heading) in steps {
+            // skip over points that are not allowed, e.g. inside the source
+            let mut next = (i, j);
+            let next = loop {
+                let (Some(ni), Some(nj)) = (next.0.checked_add_signed(di), next.1.checked_add_signed(dj)) else {
+                    break None;
+                };
+                if ni >= xs.len() || nj >= ys.len() {
+                    break None;
+                }
+                next = (ni, nj);
+                if allowed(next) {
+                    break Some(next);
+                }
+            };
+            let Some(next) = next else { continue };
+            let (a, b) = (point(node), point(next));
+            if blocked(a, b) {
+                continue;
+            }
+            let length = (a.0 - b.0).abs() + (a.1 - b.1).abs();
+            let bend = if heading.is_some_and(|heading| heading != step_heading) { BEND_PENALTY } else { 0.0 };
+            let cost = cost + scale(length + bend);
+            let key = (next, Some(step_heading));
+            if best.get(&key).is_none_or(|&best| cost < best) {
+                best.insert(key, cost);
+                previous.insert(key, (node, heading));
+                queue.push(Reverse((cost, next, Some(step_heading))));
+            }
+        }
+    }
+
+    let mut key = found?;
+    let mut points = vec![point(key.0)];
+    while let Some(&before) = previous.get(&key) {
+        points.push(point(before.0));
+        key = before;
+    }
+    points.reverse();
+
+    // only keep the corners
+    let mut corners: Vec<Point> = Vec::new();
+    for point in points {
+        if corners.len() >= 2 {
+            let (a, b) = (corners[corners.len() - 2], corners[corners.len() - 1]);
+            let collinear = ((a.0 - b.0).abs() < 1e-4 && (b.0 - point.0).abs() < 1e-4)
+                || ((a.1 - b.1).abs() < 1e-4 && (b.1 - point.1).abs() < 1e-4);
+            if collinear {
+                corners.pop();
+            }
+        }
+        corners.push(point);
+    }
+    Some(corners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32) -> Rect {
+        Rect { center: (x, y), half: (0.25, 0.25) }
+    }
+
+    #[test]
+    fn test_straight() {
+        let (a, b) = (rect(0.0, 0.0), rect(2.0, 0.0));
+        assert_eq!(route(&a, &b, &[rect(1.0, 1.0)], Routing::Auto), None);
+        assert_eq!(route(&a, &b, &[rect(1.0, 0.0)], Routing::Straight), None);
+        // aligned nodes need no corners even when routed orthogonally
+        assert_eq!(route(&a, &b, &[], Routing::Orthogonal), None);
+    }
+
+    #[test]
+    fn test_orthogonal() {
+        let (a, b) = (rect(0.0, 0.0), rect(1.0, 1.0));
+        let via = route(&a, &b, &[], Routing::Orthogonal).unwrap();
+        assert_eq!(via.len(), 1);
+        assert!(via == [(1.0, 0.0)] || via == [(0.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_avoid_obstacles() {
+        let (a, b) = (rect(0.0, 0.0), rect(2.0, 0.0));
+        let obstacles = [rect(1.0, 0.0)];
+        let via = route(&a, &b, &obstacles, Routing::Auto).unwrap();
+        let mut points = vec![a.center];
+        points.extend(&via);
+        points.push(b.center);
+        for pair in points.windows(2) {
+            let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+            assert!(x1 == x2 || y1 == y2, "{:?}", points);
+            assert!(!obstacles[0].inflate(MARGIN).intersects(pair[0], pair[1]), "{:?}", points);
+        }
+        assert_eq!(via.len(), 2);
+    }
+}
@@ -1,8 +1,15 @@
 #[cfg(target_arch = "wasm32")]
 use wasm_minimal_protocol::wasm_func;
 
+pub mod cst;
+pub mod diagnostic;
+pub mod format;
+pub mod freeze;
+pub mod layout;
 pub mod model;
 pub mod parser;
+pub mod unparse;
+pub mod validate;
 
 fn cbor_encode<T>(value: &T) -> Result<Vec<u8>, ciborium::ser::Error<std::io::Error>>
 where
@@ -28,12 +35,88 @@ wasm_minimal_protocol::initiate_protocol!();
 
 #[cfg_attr(target_arch = "wasm32", wasm_func)]
 pub fn parse(diagram: &[u8]) -> Result<Vec<u8>, String> {
-    let diagram: String = ciborium::from_reader(diagram).map_err_to_string()?;
-    let diagram = parser::parse(&diagram).map_err_to_string()?;
+    let source: String = ciborium::from_reader(diagram).map_err_to_string()?;
+    let mut diagram = parser::parse(&source)
+        .map_err(|error| diagnostic::Diagnostic::from_parse_error(&source, &error).render(&source))?;
+    diagram.resolve_references();
     let diagram = cbor_encode(&diagram).map_err_to_string()?;
     Ok(diagram)
 }
 
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn layout(diagram: &[u8], options: &[u8]) -> Result<Vec<u8>, String> {
+    let source: String = ciborium::from_reader(diagram).map_err_to_string()?;
+    let options: layout::Options = ciborium::from_reader(options).map_err_to_string()?;
+    let mut diagram = parser::parse(&source)
+        .map_err(|error| diagnostic::Diagnostic::from_parse_error(&source, &error).render(&source))?;
+    diagram.resolve_references();
+    layout::layout(&mut diagram, &options);
+    let diagram = cbor_encode(&diagram).map_err_to_string()?;
+    Ok(diagram)
+}
+
+/// The second phase of laying out a diagram: like [layout], but routes edges around the nodes
+/// using the sizes Typst measured from the rendered boxes.
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn arrange(diagram: &[u8], options: &[u8], measurements: &[u8]) -> Result<Vec<u8>, String> {
+    let source: String = ciborium::from_reader(diagram).map_err_to_string()?;
+    let options: layout::Options = ciborium::from_reader(options).map_err_to_string()?;
+    let measurements: layout::Measurements = ciborium::from_reader(measurements).map_err_to_string()?;
+    let mut diagram = parser::parse(&source)
+        .map_err(|error| diagnostic::Diagnostic::from_parse_error(&source, &error).render(&source))?;
+    diagram.resolve_references();
+    layout::arrange(&mut diagram, &options, &measurements);
+    let diagram = cbor_encode(&diagram).map_err_to_string()?;
+    Ok(diagram)
+}
+
+/// Lays out a diagram like [arrange] and writes the resulting positions and routes back into the
+/// source, which is returned. Rather than taking positions computed before, this lays the diagram
+/// out again; the layout is deterministic, so for the same source, options and measurements the
+/// positions are the ones [arrange] returns.
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn freeze(diagram: &[u8], options: &[u8], measurements: &[u8]) -> Result<Vec<u8>, String> {
+    let source: String = ciborium::from_reader(diagram).map_err_to_string()?;
+    let options: layout::Options = ciborium::from_reader(options).map_err_to_string()?;
+    let measurements: layout::Measurements = ciborium::from_reader(measurements).map_err_to_string()?;
+    let mut diagram = parser::parse(&source)
+        .map_err(|error| diagnostic::Diagnostic::from_parse_error(&source, &error).render(&source))?;
+    diagram.resolve_references();
+    layout::arrange(&mut diagram, &options, &measurements);
+    let source = cbor_encode(&freeze::freeze(&source, &diagram)).map_err_to_string()?;
+    Ok(source)
+}
+
+/// Formats the source of a diagram; see [format::format]. Sources with syntax errors are rejected.
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn format(diagram: &[u8], options: &[u8]) -> Result<Vec<u8>, String> {
+    let source: String = ciborium::from_reader(diagram).map_err_to_string()?;
+    let options: format::Options = ciborium::from_reader(options).map_err_to_string()?;
+    parser::parse(&source).map_err(|error| diagnostic::Diagnostic::from_parse_error(&source, &error).render(&source))?;
+    let source = cbor_encode(&format::format(&source, &options)).map_err_to_string()?;
+    Ok(source)
+}
+
+/// Turns a diagram back into source; see [unparse::unparse].
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn unparse(diagram: &[u8]) -> Result<Vec<u8>, String> {
+    let diagram: ciborium::Value = ciborium::from_reader(diagram).map_err_to_string()?;
+    let source = unparse::unparse(&diagram).map_err_to_string()?;
+    let source = cbor_encode(&source).map_err_to_string()?;
+    Ok(source)
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_func)]
+pub fn validate(diagram: &[u8]) -> Result<Vec<u8>, String> {
+    let source: String = ciborium::from_reader(diagram).map_err_to_string()?;
+    let diagnostics = match parser::parse(&source) {
+        Ok(diagram) => validate::validate(&source, &diagram),
+        Err(error) => vec![diagnostic::Diagnostic::from_parse_error(&source, &error)],
+    };
+    let diagnostics = cbor_encode(&diagnostics).map_err_to_string()?;
+    Ok(diagnostics)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,4 +139,287 @@ mod tests {
         )
         .unwrap();
     }
+
+    #[test]
+    fn test_layout() {
+        let options = cbor_encode(&layout::Options::default()).unwrap();
+        let diagram = layout(&cbor_encode("class A\nclass B\nB --|> A").unwrap(), &options).unwrap();
+        let diagram: ciborium::Value = ciborium::from_reader(&diagram[..]).unwrap();
+        let pos = |i: usize| {
+            let classifiers = diagram.as_map().unwrap().iter().find(|(k, _)| k.as_text() == Some("classifiers")).unwrap();
+            let classifier = classifiers.1.as_array().unwrap()[i].as_map().unwrap();
+            let pos = classifier.iter().find(|(k, _)| k.as_text() == Some("pos")).unwrap();
+            let pos = pos.1.as_array().unwrap().iter().map(|x| x.as_float().unwrap());
+            pos.collect::<Vec<_>>()
+        };
+        assert_eq!(pos(0), [0.0, 0.0]);
+        assert_eq!(pos(1), [0.0, 1.0]);
+
+        let options = |algorithm| cbor_encode(&std::collections::BTreeMap::from([("algorithm", algorithm)])).unwrap();
+        layout(&cbor_encode("class A").unwrap(), &options("force")).unwrap();
+        layout(&cbor_encode("class A").unwrap(), &options("random")).unwrap_err();
+    }
+
+    #[test]
+    fn test_arrange() {
+        let options = cbor_encode(&layout::Options::default()).unwrap();
+        let measurements = cbor_encode(&layout::Measurements {
+            sizes: std::collections::BTreeMap::from([("A".to_string(), (40.0, 20.0))]),
+            spacing: 30.0,
+        })
+        .unwrap();
+        let diagram = arrange(&cbor_encode("class A\nclass B\nB --|> A").unwrap(), &options, &measurements).unwrap();
+        let diagram: ciborium::Value = ciborium::from_reader(&diagram[..]).unwrap();
+        assert!(diagram.as_map().is_some());
+
+        arrange(&cbor_encode("class A").unwrap(), &options, &cbor_encode("sizes").unwrap()).unwrap_err();
+    }
+
+    #[test]
+    fn test_freeze() {
+        let options = cbor_encode(&layout::Options::default()).unwrap();
+        let measurements = cbor_encode(&layout::Measurements::default()).unwrap();
+        let source = freeze(&cbor_encode("class A\n// B\nclass B").unwrap(), &options, &measurements).unwrap();
+        let source: String = ciborium::from_reader(&source[..]).unwrap();
+        assert_eq!(source, "#[pos(0, 0)]\nclass A\n// B\n#[pos(1, 0)]\nclass B");
+    }
+
+    #[test]
+    fn test_unparse() {
+        let sources = [
+            "«entity» class Order<T: Comparable = Integer> as O {\n  - {static} items: \"List<Item>\"[0..*] = [] {ordered}\n  + total(in x: Int = 1): Money throws IOException {query}\n}\nO .«bind» <T -> String>.> O",
+            "class \"note\"\nclass \"a b\" as X {\n  / age: Int\n  {static} + f(out x: Int[1..*] = 0 {ordered}): Bool {query}\n}",
+            "abstract interface I<T>\nenumeration E { A; B = \"b\" }\nI \"*\" o-- r: E : < has\nE .«use» name.> I",
+            "#[from(east), via(A.north, (0, 1))]\nA -- B\n#[loop(45deg), bend(1rad)]\nA -- A",
+            "package p {\n  class A {\n    x\n  }\n  package q {\n    class B\n  }\n  A -- q::B\n}\nnote on p::A::x, p::A -- p::q::B \"n\"",
+        ];
+        for source in sources {
+            assert!(parse(&cbor_encode(source).unwrap()).is_ok(), "{source:?} is invalid");
+            check_unparse(source);
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let source = "class A\nclass A\n#[right-of(C)]\nclass D\nA -- B\nnote on A::x \"n\"";
+        let diagnostics = validate(&cbor_encode(source).unwrap()).unwrap();
+        let diagnostics: Vec<diagnostic::Diagnostic> = ciborium::from_reader(&diagnostics[..]).unwrap();
+        let diagnostics = diagnostics.iter().map(|x| x.render(source)).collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            [
+                "error: duplicate classifier `A`\n --> 2:1\n  |\n2 | class A\n  | ^^^^^^^",
+                "error: unresolved reference `C`\n --> 3:1\n  |\n3 | #[right-of(C)]\n  | ^^^^^^^^^^^^^^",
+                "error: unresolved reference `B`\n --> 5:1\n  |\n5 | A -- B\n  | ^^^^^^",
+                "error: unresolved reference `A::x`\n --> 6:1\n  |\n6 | note on A::x \"n\"\n  | ^^^^^^^^^^^^^^^^",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = parse(&cbor_encode("class A {\n  x[3..1]\n}").unwrap()).unwrap_err();
+        assert_eq!(error, "error: upper bound is less than lower bound\n --> 2:5\n  |\n2 |   x[3..1]\n  |     ^^^^");
+
+        let diagnostics = validate(&cbor_encode("class A class B").unwrap()).unwrap();
+        let diagnostics: Vec<diagnostic::Diagnostic> = ciborium::from_reader(&diagnostics[..]).unwrap();
+        let [diagnostic] = &diagnostics[..] else { panic!("expected exactly one diagnostic") };
+        assert_eq!(diagnostic.message, "unexpected `class`");
+        assert_eq!(diagnostic.span.as_ref().unwrap().start.column, 9);
+        assert_eq!(diagnostic.expected, ["newline", "`<`", "`as`", "`{`"]);
+        assert!(diagnostic.hint.is_some());
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let mut diagram = parser::parse(
+            r#"
+            class A
+            package p {
+                class A
+                class B as X
+                package q {
+                    class C
+                    C -- A
+                    C -- q::C
+                    C -- p::q::C
+                }
+                A -- X
+                A -- q::C
+                A -- Z
+            }
+            A -- p::A
+            "#,
+        )
+        .unwrap();
+        diagram.resolve_references();
+
+        let endpoints = |edges: &[model::Edge<'_>]| {
+            edges
+                .iter()
+                .map(|edge| format!("{} {}", edge.a, edge.b))
+                .collect::<Vec<_>>()
+        };
+        let p = &diagram.packages[0];
+        let q = &p.packages[0];
+        assert_eq!(endpoints(&diagram.edges), ["A p::A"]);
+        assert_eq!(endpoints(&p.edges), ["p::A p::X", "p::A p::q::C", "p::A Z"]);
+        assert_eq!(endpoints(&q.edges), ["p::q::C p::A", "p::q::C p::q::C", "p::q::C p::q::C"]);
+    }
+
+    #[test]
+    fn test_resolve_note_anchors() {
+        let mut diagram = parser::parse(
+            r#"
+            package p {
+                class A {
+                    - x
+                }
+                note on A, A::x, A::y, A -- B "foo"
+            }
+            "#,
+        )
+        .unwrap();
+        diagram.resolve_references();
+
+        let anchors = &diagram.packages[0].notes[0].anchors;
+        assert_eq!(anchors[0], model::NoteAnchor::Classifier { id: "p::A".into() });
+        assert_eq!(
+            anchors[1],
+            model::NoteAnchor::Member {
+                classifier: "p::A".into(),
+                member: "x".into(),
+            },
+        );
+        assert_eq!(anchors[2], model::NoteAnchor::Classifier { id: "A::y".into() });
+        assert_eq!(
+            anchors[3],
+            model::NoteAnchor::Edge {
+                a: "p::A".into(),
+                b: "B".into(),
+                a_end: Default::default(),
+                b_end: Default::default(),
+            },
+        );
+    }
+
+    /// A small deterministic pseudo-random number generator (xorshift), so that the fuzz tests
+    /// below are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn check_no_panic(input: &[u8]) {
+        let _ = parse(input);
+        let _ = layout(input, &cbor_encode(&layout::Options::default()).unwrap());
+        let _ = layout(&cbor_encode("class A").unwrap(), input);
+        let options = cbor_encode(&layout::Options::default()).unwrap();
+        let _ = arrange(&cbor_encode("class A").unwrap(), &options, input);
+        let measurements = cbor_encode(&layout::Measurements::default()).unwrap();
+        let _ = freeze(input, &options, &measurements);
+        let _ = format(input, &cbor_encode(&format::Options::default()).unwrap());
+        let _ = format(&cbor_encode("class A").unwrap(), input);
+        let _ = unparse(input);
+        let _ = validate(input);
+    }
+
+    /// Checks that formatting a valid source results in a valid source, which doesn't change when
+    /// formatted again.
+    fn check_format(source: &str) {
+        if parser::parse(source).is_err() {
+            return;
+        }
+        let all = format::Options { indent: 4, align_colons: true, sort_members: true, normalize_metas: true };
+        for options in [format::Options::default(), all] {
+            let formatted = format::format(source, &options);
+            assert!(parser::parse(&formatted).is_ok(), "{source:?} was formatted to the invalid {formatted:?}");
+            assert_eq!(format::format(&formatted, &options), formatted);
+        }
+    }
+
+    /// Checks that a valid source, parsed and turned back into source, is parsed to the same result.
+    fn check_unparse(source: &str) {
+        let Ok(parsed) = parse(&cbor_encode(source).unwrap()) else {
+            return;
+        };
+        let unparsed = unparse(&parsed).unwrap_or_else(|error| panic!("{source:?} could not be unparsed: {error}"));
+        let unparsed: String = ciborium::from_reader(unparsed.as_slice()).unwrap();
+        let reparsed = parse(&cbor_encode(&unparsed).unwrap());
+        assert_eq!(reparsed.as_ref(), Ok(&parsed), "{source:?} was unparsed to {unparsed:?}");
+    }
+
+    const SAMPLE: &str = r##"
+        #[pos(0, 1)]
+        «entity» abstract class Order<T: Comparable = Integer> as O {
+          - {static} items: "List<Item>"[0..*] = [] {ordered}
+          + total(in x: Int = 1): Money throws IOException {query}
+        }
+        enumeration Color { RED("#f00") = 1; GREEN }
+        package billing {
+          class "Invoice \u{41}" as I
+        }
+        #[via((1, 0.4), (2, 0.4)), bend(-45deg)]
+        O "1" <--x-* "0..*" items: billing::I : contains >
+        O .«bind» <T -> String>.> billing::I
+        note on O::total, O -- billing::I "text\n"
+    "##;
+
+    #[test]
+    fn test_fuzz_bytes() {
+        let mut rng = Rng(0x5eed);
+        for _ in 0..2000 {
+            let len = rng.below(64);
+            let bytes = (0..len).map(|_| rng.next() as u8).collect::<Vec<_>>();
+            check_no_panic(&bytes);
+
+            // a valid CBOR text header followed by random (likely invalid UTF-8) bytes
+            let mut text = vec![0x60 + len.min(23) as u8];
+            text.extend(&bytes[..len.min(23)]);
+            check_no_panic(&text);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_sources() {
+        // every prefix of a valid diagram
+        assert!(parser::parse(SAMPLE).is_ok());
+        for (index, _) in SAMPLE.char_indices() {
+            check_no_panic(&cbor_encode(&SAMPLE[..index]).unwrap());
+            assert_eq!(cst::parse(&SAMPLE[..index]).to_string(), &SAMPLE[..index]);
+            check_format(&SAMPLE[..index]);
+            check_unparse(&SAMPLE[..index]);
+        }
+
+        // random sequences of tokens, including tricky ones
+        const TOKENS: &[&str] = &[
+            "class", "A", "B", "A::B", "::", "{", "}", "(", ")", "[", "]", "<", ">", "«", "»", "<<", ">>",
+            ":", "x:", ",", ";", ".", "..", "*", "=", "/", "+", "-", "#", "~", "#[", "pos", "via", "bend",
+            "0", "-1", "1.5", "99999999999999999999999", "1e40", "45deg", "1.0rad", "3grad",
+            "99999999999999999999999999999999999999999.0deg", "--", "<--", "x-o--*", "o--x->", "<-x--",
+            "--|>", "<|..", "..>", ".", ".>", "<.", "->", "\"", "\"a\"", "\"\\u{110000}\"", "\"\\u{\"",
+            "\"\\\"", "\\", "note", "on", "package", "enumeration", "static", "abstract", "throws", "\n",
+            " ", "ä", "\u{0}", "\r",
+        ];
+        let mut rng = Rng(0xc0ffee);
+        for _ in 0..2000 {
+            let len = rng.below(24);
+            let source = (0..len).map(|_| TOKENS[rng.below(TOKENS.len())]).collect::<Vec<_>>();
+            check_no_panic(&cbor_encode(&source.join(" ")).unwrap());
+            check_no_panic(&cbor_encode(&source.concat()).unwrap());
+            assert_eq!(cst::parse(&source.concat()).to_string(), source.concat());
+            check_unparse(&source.join(" "));
+            check_unparse(&source.concat());
+        }
+    }
 }
@@ -1,29 +1,182 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::f32::consts::PI;
 use std::fmt;
 
-use either::Either;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
 use serde::{Deserialize, Serialize};
 
-mod helpers;
+pub(crate) mod helpers;
 
 mod classifier;
 mod edge;
+mod note;
+mod package;
 
 pub use classifier::*;
 pub use edge::*;
+pub use note::*;
+pub use package::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(bound(deserialize = "'de: 'input"))]
 pub struct Diagram<'input> {
+    #[serde(default)]
     pub classifiers: Vec<Classifier<'input>>,
+    #[serde(default)]
     pub edges: Vec<Edge<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<Package<'input>>,
+}
+
+impl<'input> Diagram<'input> {
+    /// Replaces references to classifiers in edges and notes by the qualified ids of the
+    /// classifiers they refer to. A name used inside a package is looked up in that package first,
+    /// then in the enclosing packages. Classifiers with an `as` id can be referred to by the id as
+    /// well as by their name, but ids take precedence. References that can't be resolved are left
+    /// unchanged.
+    pub fn resolve_references(&mut self) {
+        let ids = self.qualified_ids();
+        let mut aliases = BTreeMap::new();
+        collect_aliases(&mut aliases, "", &self.classifiers, &self.packages);
+        let mut scope = Vec::new();
+        resolve_references(
+            &ids,
+            &aliases,
+            &mut scope,
+            &mut self.classifiers,
+            &mut self.edges,
+            &mut self.notes,
+            &mut self.packages,
+        );
+    }
+
+    /// Returns the qualified ids of all classifiers, mapped to the names of their members.
+    pub fn qualified_ids(&self) -> QualifiedIds {
+        let mut ids = BTreeMap::new();
+        collect_qualified_ids(&mut ids, "", &self.classifiers, &self.packages);
+        ids
+    }
+}
+
+/// Returns the qualified name of `name` within the package `prefix`, e.g. `billing::Invoice`.
+pub fn qualify(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", prefix, name)
+    }
+}
+
+/// maps the qualified ids of all classifiers to the names of their members
+pub type QualifiedIds = BTreeMap<String, BTreeSet<String>>;
+
+fn collect_qualified_ids(
+    ids: &mut QualifiedIds,
+    prefix: &str,
+    classifiers: &[Classifier<'_>],
+    packages: &[Package<'_>],
+) {
+    for classifier in classifiers {
+        let literals = classifier.literals.iter().map(|x| x.name);
+        let attributes = classifier.attributes.iter().map(|x| x.name);
+        let operations = classifier.operations.iter().map(|x| x.name);
+        let members = literals.chain(attributes).chain(operations).map(str::to_string).collect();
+        ids.insert(qualify(prefix, classifier.id.unwrap_or(&classifier.name)), members);
+    }
+    for package in packages {
+        let prefix = qualify(prefix, package.name);
+        collect_qualified_ids(ids, &prefix, &package.classifiers, &package.packages);
+    }
+}
+
+/// maps the qualified names of classifiers with an `as` id to their qualified ids
+fn collect_aliases(aliases: &mut BTreeMap<String, String>, prefix: &str, classifiers: &[Classifier<'_>], packages: &[Package<'_>]) {
+    for classifier in classifiers {
+        if let Some(id) = classifier.id {
+            aliases.insert(qualify(prefix, &classifier.name), qualify(prefix, id));
+        }
+    }
+    for package in packages {
+        let prefix = qualify(prefix, package.name);
+        collect_aliases(aliases, &prefix, &package.classifiers, &package.packages);
+    }
+}
+
+fn resolve_references<'input>(
+    ids: &QualifiedIds,
+    aliases: &BTreeMap<String, String>,
+    scope: &mut Vec<&'input str>,
+    classifiers: &mut [Classifier<'input>],
+    edges: &mut [Edge<'input>],
+    notes: &mut [Note<'input>],
+    packages: &mut [Package<'input>],
+) {
+    let lookup = |name: &str| {
+        (0..=scope.len())
+            .rev()
+            .map(|i| qualify(&scope[..i].join("::"), name))
+            .find_map(|name| if ids.contains_key(&name) { Some(name) } else { aliases.get(&name).cloned() })
+    };
+    let resolve = |name: &mut Cow<'input, str>| {
+        if let Some(id) = lookup(name) {
+            *name = Cow::Owned(id);
+        }
+    };
+    let metas = classifiers.iter_mut().map(|x| &mut x.meta).chain(notes.iter_mut().map(|x| &mut x.meta));
+    let metas = metas.chain(edges.iter_mut().map(|x| &mut x.meta));
+    for meta in metas.flat_map(|meta| meta.values_mut()) {
+        meta.references_mut().into_iter().for_each(resolve);
+    }
+    for edge in edges {
+        resolve(&mut edge.a);
+        resolve(&mut edge.b);
+    }
+    for anchor in notes.iter_mut().flat_map(|note| &mut note.anchors) {
+        match anchor {
+            NoteAnchor::Classifier { id } => {
+                if let Some(resolved) = lookup(id) {
+                    *id = Cow::Owned(resolved);
+                } else if let Some((classifier, member)) = id.rsplit_once("::") {
+                    let classifier = lookup(classifier).filter(|classifier| ids[classifier].contains(member));
+                    if let Some(classifier) = classifier {
+                        let member = Cow::Owned(member.to_string());
+                        let classifier = Cow::Owned(classifier);
+                        *anchor = NoteAnchor::Member { classifier, member };
+                    }
+                }
+            }
+            NoteAnchor::Member { classifier, .. } => resolve(classifier),
+            NoteAnchor::Edge { a, b, .. } => {
+                resolve(a);
+                resolve(b);
+            }
+        }
+    }
+    for package in packages {
+        scope.push(package.name);
+        resolve_references(
+            ids,
+            aliases,
+            scope,
+            &mut package.classifiers,
+            &mut package.edges,
+            &mut package.notes,
+            &mut package.packages,
+        );
+        scope.pop();
+    }
 }
 
 impl fmt::Display for Diagram<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let classifiers = self.classifiers.iter().map(Either::Left);
-        let edges = self.edges.iter().map(Either::Right);
-        let mut items = classifiers.chain(edges);
+        let classifiers = self.classifiers.iter().map(|x| x as &dyn fmt::Display);
+        let packages = self.packages.iter().map(|x| x as &dyn fmt::Display);
+        let edges = self.edges.iter().map(|x| x as &dyn fmt::Display);
+        let notes = self.notes.iter().map(|x| x as &dyn fmt::Display);
+        let mut items = classifiers.chain(packages).chain(edges).chain(notes);
         if let Some(x) = items.next() {
             write!(f, "{}", x)?;
             for x in items {
@@ -35,25 +188,170 @@ impl fmt::Display for Diagram<'_> {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-// #[serde(bound(deserialize = "'de: 'input"))]
+#[serde(bound(deserialize = "'de: 'input"))]
 #[serde(untagged)]
-pub enum Meta {
+pub enum Meta<'input> {
     Position(f32, f32),
     Via(Vec<(f32, f32)>),
     Bend(f32),
+    /// placed the given number of grid cells to the right of a classifier
+    RightOf(Cow<'input, str>, f32),
+    LeftOf(Cow<'input, str>, f32),
+    Above(Cow<'input, str>, f32),
+    Below(Cow<'input, str>, f32),
+    /// placed in the same column as the given classifiers
+    AlignX(Vec<Cow<'input, str>>),
+    /// placed in the same row as the given classifiers
+    AlignY(Vec<Cow<'input, str>>),
+    /// how an edge is routed around other nodes
+    Route(Routing),
+    /// the direction in which a self-association leaves and enters its classifier
+    Loop(f32),
+    /// `via` points of which some are relative to classifiers, and the absolute points they were
+    /// resolved to by the layout. Only the latter are serialized, or the former before the layout
+    #[serde(serialize_with = "serialize_via")]
+    AnchoredVia(Vec<Anchor<'input>>, Vec<(f32, f32)>),
+    /// the side of the first classifier an edge leaves from, and the point outside that side the
+    /// edge is led through. Only the latter is serialized, or the former before the layout
+    #[serde(serialize_with = "serialize_side")]
+    From(Side, Option<(f32, f32)>),
+    /// the side of the second classifier an edge enters, like [Meta::From]
+    #[serde(serialize_with = "serialize_side")]
+    To(Side, Option<(f32, f32)>),
+}
+
+fn serialize_via<S: serde::Serializer>(anchors: &Vec<Anchor<'_>>, resolved: &Vec<(f32, f32)>, serializer: S) -> Result<S::Ok, S::Error> {
+    if resolved.is_empty() {
+        anchors.serialize(serializer)
+    } else {
+        resolved.serialize(serializer)
+    }
+}
+
+fn serialize_side<S: serde::Serializer>(side: &Side, resolved: &Option<(f32, f32)>, serializer: S) -> Result<S::Ok, S::Error> {
+    match resolved {
+        Some(point) => point.serialize(serializer),
+        None => side.serialize(serializer),
+    }
 }
 
-impl Meta {
+/// Deserializes the metas of an element by their names. [Meta] is untagged, so on its own it
+/// can't tell apart metas of the same shape, such as `bend` and `loop`. Metas that are only
+/// resolved by the layout are expected in their unresolved form.
+pub fn deserialize_metas<'de: 'input, 'input, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<&'input str, Meta<'input>>, D::Error> {
+    struct MetasVisitor;
+
+    #[derive(Deserialize)]
+    #[serde(untagged, bound(deserialize = "'de: 'input"))]
+    enum ViaPoint<'input> {
+        Point((f32, f32)),
+        Anchor(Anchor<'input>),
+    }
+
+    impl<'de> Visitor<'de> for MetasVisitor {
+        type Value = BTreeMap<&'de str, Meta<'de>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "metas")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut metas = BTreeMap::new();
+            while let Some(name) = map.next_key::<Cow<'de, str>>()? {
+                let meta = match &*name {
+                    "pos" => map.next_value().map(|(x, y)| Meta::Position(x, y))?,
+                    "via" => {
+                        let anchors: Vec<_> = map
+                            .next_value::<Vec<ViaPoint<'de>>>()?
+                            .into_iter()
+                            .map(|point| match point {
+                                ViaPoint::Point(offset) => Anchor { reference: None, side: None, offset },
+                                ViaPoint::Anchor(anchor) => anchor,
+                            })
+                            .collect();
+                        // like the parser, only use anchors if some points are relative
+                        if anchors.iter().all(|anchor| anchor.reference.is_none()) {
+                            Meta::Via(anchors.into_iter().map(|anchor| anchor.offset).collect())
+                        } else {
+                            Meta::AnchoredVia(anchors, Vec::new())
+                        }
+                    },
+                    "bend" => Meta::Bend(map.next_value()?),
+                    "loop" => Meta::Loop(map.next_value()?),
+                    "right-of" => map.next_value().map(|(reference, distance)| Meta::RightOf(reference, distance))?,
+                    "left-of" => map.next_value().map(|(reference, distance)| Meta::LeftOf(reference, distance))?,
+                    "above" => map.next_value().map(|(reference, distance)| Meta::Above(reference, distance))?,
+                    "below" => map.next_value().map(|(reference, distance)| Meta::Below(reference, distance))?,
+                    "align-x" => Meta::AlignX(map.next_value()?),
+                    "align-y" => Meta::AlignY(map.next_value()?),
+                    "route" => Meta::Route(map.next_value()?),
+                    "from" => Meta::From(map.next_value()?, None),
+                    "to" => Meta::To(map.next_value()?, None),
+                    name => return Err(de::Error::custom(format_args!("unknown meta `{name}`"))),
+                };
+                metas.insert(meta.name(), meta);
+            }
+            Ok(metas)
+        }
+    }
+
+    deserializer.deserialize_map(MetasVisitor)
+}
+
+impl<'input> Meta<'input> {
     pub fn name(&self) -> &'static str {
         match self {
             Self::Position(_, _) => "pos",
             Self::Via(_) => "via",
             Self::Bend(_) => "bend",
+            Self::RightOf(_, _) => "right-of",
+            Self::LeftOf(_, _) => "left-of",
+            Self::Above(_, _) => "above",
+            Self::Below(_, _) => "below",
+            Self::AlignX(_) => "align-x",
+            Self::AlignY(_) => "align-y",
+            Self::Route(_) => "route",
+            Self::Loop(_) => "loop",
+            Self::AnchoredVia(_, _) => "via",
+            Self::From(_, _) => "from",
+            Self::To(_, _) => "to",
+        }
+    }
+
+    /// Returns the classifiers this meta positions an element or `via` points relative to.
+    pub fn references(&self) -> Vec<&Cow<'input, str>> {
+        match self {
+            Self::RightOf(reference, _)
+            | Self::LeftOf(reference, _)
+            | Self::Above(reference, _)
+            | Self::Below(reference, _) => vec![reference],
+            Self::AlignX(references) | Self::AlignY(references) => references.iter().collect(),
+            Self::AnchoredVia(anchors, _) => anchors.iter().filter_map(|anchor| anchor.reference.as_ref()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn references_mut(&mut self) -> Vec<&mut Cow<'input, str>> {
+        match self {
+            Self::RightOf(reference, _)
+            | Self::LeftOf(reference, _)
+            | Self::Above(reference, _)
+            | Self::Below(reference, _) => vec![reference],
+            Self::AlignX(references) | Self::AlignY(references) => references.iter_mut().collect(),
+            Self::AnchoredVia(anchors, _) => anchors.iter_mut().filter_map(|anchor| anchor.reference.as_mut()).collect(),
+            _ => Vec::new(),
         }
     }
+
+    /// Whether this meta positions an element relative to other classifiers.
+    pub fn is_relative(&self) -> bool {
+        !self.references().is_empty()
+    }
 }
 
-impl fmt::Display for Meta {
+impl fmt::Display for Meta<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}(", self.name())?;
         match self {
@@ -67,15 +365,148 @@ impl fmt::Display for Meta {
                     }
                 }
             },
-            Self::Bend(angle) => {
-                write!(f, "{}deg", angle * 180.0 / PI)?;
+            Self::Bend(angle) | Self::Loop(angle) => {
+                // the conversion from radians shows floating point noise, e.g. `45.000004deg`, so
+                // degrees are rounded, and only used if they are parsed back to the same angle
+                let degrees = (angle * 180.0 / PI * 1000.0).round() / 1000.0;
+                if degrees * PI / 180.0 == *angle {
+                    write!(f, "{}deg", degrees)?;
+                } else {
+                    write!(f, "{}rad", angle)?;
+                }
+            },
+            Self::RightOf(reference, distance)
+            | Self::LeftOf(reference, distance)
+            | Self::Above(reference, distance)
+            | Self::Below(reference, distance) => {
+                write!(f, "{}", reference)?;
+                if *distance != 1.0 {
+                    write!(f, ", {}", distance)?;
+                }
+            },
+            Self::AlignX(references) | Self::AlignY(references) => {
+                write!(f, "{}", references.join(", "))?;
+            },
+            Self::Route(routing) => write!(f, "{}", routing)?,
+            Self::AnchoredVia(anchors, _) => {
+                let anchors: Vec<_> = anchors.iter().map(ToString::to_string).collect();
+                write!(f, "{}", anchors.join(", "))?;
             },
+            Self::From(side, _) | Self::To(side, _) => write!(f, "{}", side)?,
         }
         write!(f, ")")?;
         Ok(())
     }
 }
 
+/// How an edge is routed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Routing {
+    /// orthogonal, if the straight line would cross other nodes
+    #[default]
+    Auto,
+    /// horizontal and vertical segments around other nodes
+    Orthogonal,
+    /// a straight line, or as specified by `via` and `bend`
+    Straight,
+}
+
+impl fmt::Display for Routing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Orthogonal => write!(f, "orthogonal"),
+            Self::Straight => write!(f, "straight"),
+        }
+    }
+}
+
+/// A side of a classifier's box.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Side {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Side {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "north" => Some(Self::North),
+            "east" => Some(Self::East),
+            "south" => Some(Self::South),
+            "west" => Some(Self::West),
+            _ => None,
+        }
+    }
+
+    /// The direction from a box's center to this side, with `y` pointing down.
+    pub fn direction(self) -> (f32, f32) {
+        match self {
+            Self::North => (0.0, -1.0),
+            Self::East => (1.0, 0.0),
+            Self::South => (0.0, 1.0),
+            Self::West => (-1.0, 0.0),
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::North => write!(f, "north"),
+            Self::East => write!(f, "east"),
+            Self::South => write!(f, "south"),
+            Self::West => write!(f, "west"),
+        }
+    }
+}
+
+/// A point given relative to a classifier, such as `Order.east + (0.5, 0)`, or an absolute point
+/// if there is no reference.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(bound(deserialize = "'de: 'input"))]
+pub struct Anchor<'input> {
+    pub reference: Option<Cow<'input, str>>,
+    /// the side of the classifier's box; the center if there is none
+    pub side: Option<Side>,
+    #[serde(default)]
+    pub offset: (f32, f32),
+}
+
+impl fmt::Display for Anchor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.offset;
+        let Some(reference) = &self.reference else {
+            return write!(f, "({x}, {y})");
+        };
+        write!(f, "{}", reference)?;
+        if let Some(side) = self.side {
+            write!(f, ".{}", side)?;
+        }
+        if self.offset != (0.0, 0.0) {
+            write!(f, " + ({x}, {y})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where an element and its metas are in the source, so that the metas can be rewritten (see
+/// [crate::freeze]) and diagnostics can point at the element.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MetaSpan {
+    /// the byte range of the `#[...]` block, if there is one
+    pub metas: Option<(usize, usize)>,
+    /// the byte offset at which the element itself starts
+    pub start: usize,
+    /// the byte offset at which the element ends; for classifiers, the end of the head, without
+    /// the body
+    pub end: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum Visibility {
     #[serde(rename = "-")]
@@ -98,3 +529,26 @@ impl fmt::Display for Visibility {
         }
     }
 }
+
+/// A [multiplicity](https://www.uml-diagrams.org/multiplicity.html) of an attribute or
+/// parameter, e.g. `[1]` or `[0..*]`. An upper bound of `None` means unlimited (`*`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Multiplicity {
+    pub lower: usize,
+    pub upper: Option<usize>,
+    /// whether `[0..*]` is written as the shorthand `[*]`
+    #[serde(default, skip_serializing_if = "helpers::is_false")]
+    pub shorthand: bool,
+}
+
+impl fmt::Display for Multiplicity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.lower, self.upper) {
+            (0, None) if self.shorthand => write!(f, "[*]"),
+            (lower, Some(upper)) if upper == lower => write!(f, "[{}]", upper),
+            (lower, Some(upper)) => write!(f, "[{}..{}]", lower, upper),
+            (lower, None) => write!(f, "[{}..*]", lower),
+        }
+    }
+}
@@ -1,49 +1,53 @@
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-use super::{helpers, Meta};
+use super::{helpers, Meta, MetaSpan};
 
 mod attribute;
+mod literal;
 mod operation;
+mod template;
 
 pub use attribute::Attribute;
-pub use operation::{Operation, Parameter};
+pub use literal::EnumerationLiteral;
+pub use operation::{Operation, Parameter, ParameterDirection};
+pub use template::TemplateParameter;
 
 /// A [classifier](https://www.uml-diagrams.org/classifier.html).
 /// See [ClassKind] for the supported kinds of classifiers.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Classifier<'input> {
-    #[serde(flatten)]
-    pub meta: BTreeMap<&'input str, Meta>,
-    #[serde(rename = "abstract", skip_serializing_if = "helpers::is_false")]
+    #[serde(flatten, deserialize_with = "super::deserialize_metas")]
+    pub meta: BTreeMap<&'input str, Meta<'input>>,
+    #[serde(rename = "abstract", default, skip_serializing_if = "helpers::is_false")]
     pub is_abstract: bool,
-    #[serde(rename = "final", skip_serializing_if = "helpers::is_false")]
+    #[serde(rename = "final", default, skip_serializing_if = "helpers::is_false")]
     pub is_final: bool,
     pub kind: ClassifierKind,
-    pub name: &'input str,
+    pub name: Cow<'input, str>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub template_parameters: Vec<TemplateParameter<'input>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<&'input str>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub stereotypes: Vec<&'input str>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub literals: Vec<EnumerationLiteral<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub attributes: Vec<Attribute<'input>>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub operations: Vec<Operation<'input>>,
+    #[serde(skip)]
+    pub span: MetaSpan,
 }
 
 impl fmt::Display for Classifier<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut meta = self.meta.values();
-        if let Some(x) = meta.next() {
-            write!(f, "#[{}", x)?;
-            for x in meta {
-                write!(f, ", {}", x)?;
-            }
-            write!(f, "]\n")?;
-        }
+        helpers::fmt_metas(f, &self.meta)?;
 
         if self.is_abstract && self.kind != ClassifierKind::Interface {
             write!(f, "abstract ")?;
@@ -71,13 +75,24 @@ impl fmt::Display for Classifier<'_> {
             }
             write!(f, "» ")?;
         }
-        write!(f, "{} {}", self.kind, self.name)?;
+        write!(f, "{} {}", self.kind, helpers::NameOrQuoted(&self.name))?;
+        let mut template_parameters = self.template_parameters.iter();
+        if let Some(x) = template_parameters.next() {
+            write!(f, "<{}", x)?;
+            for x in template_parameters {
+                write!(f, ", {}", x)?;
+            }
+            write!(f, ">")?;
+        }
         if let Some(id) = self.id {
             write!(f, " as {}", id)?;
         }
 
-        if !self.attributes.is_empty() || !self.operations.is_empty() {
+        if !self.literals.is_empty() || !self.attributes.is_empty() || !self.operations.is_empty() {
             write!(f, " {{")?;
+            for literal in &self.literals {
+                write!(f, "\n  {}", literal)?;
+            }
             for attr in &self.attributes {
                 write!(f, "\n  {}", attr)?;
             }
@@ -3,16 +3,27 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-use crate::model::Visibility;
+use crate::model::{helpers, Multiplicity, Visibility};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Attribute<'input> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub visibility: Option<Visibility>,
+    #[serde(rename = "static", default, skip_serializing_if = "helpers::is_false")]
+    pub is_static: bool,
+    #[serde(rename = "derived", default, skip_serializing_if = "helpers::is_false")]
+    pub is_derived: bool,
     pub name: &'input str,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#type: Option<Cow<'input, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplicity: Option<Multiplicity>,
+    /// the default value, as the expression was written in the source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Cow<'input, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Cow<'input, str>>,
 }
 
 impl fmt::Display for Attribute<'_> {
@@ -20,10 +31,19 @@ impl fmt::Display for Attribute<'_> {
         if let Some(visibility) = self.visibility {
             write!(f, "{} ", visibility)?;
         }
-        write!(f, "{}", self.name)?;
-        if let Some(r#type) = &self.r#type {
-            write!(f, ": {}", r#type)?;
+        if self.is_static {
+            write!(f, "{{static}} ")?;
         }
-        Ok(())
+        if self.is_derived {
+            write!(f, "/")?;
+        }
+        write!(f, "{}", self.name)?;
+        helpers::fmt_typed_element(
+            f,
+            self.r#type.as_ref(),
+            self.multiplicity.as_ref(),
+            self.default.as_ref(),
+            &self.properties,
+        )
     }
 }
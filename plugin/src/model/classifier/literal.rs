@@ -0,0 +1,37 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// An [enumeration literal](https://www.uml-diagrams.org/enumeration.html), optionally with
+/// constructor arguments and/or a value, as in `RED("#f00") = 1`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnumerationLiteral<'input> {
+    pub name: &'input str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Vec<Cow<'input, str>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Cow<'input, str>>,
+}
+
+impl fmt::Display for EnumerationLiteral<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(arguments) = &self.arguments {
+            write!(f, "(")?;
+            let mut arguments = arguments.iter();
+            if let Some(x) = arguments.next() {
+                write!(f, "{}", x)?;
+                for x in arguments {
+                    write!(f, ", {}", x)?;
+                }
+            }
+            write!(f, ")")?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " = {}", value)?;
+        }
+        Ok(())
+    }
+}
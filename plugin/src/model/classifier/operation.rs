@@ -3,17 +3,28 @@ use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-use crate::model::Visibility;
+use crate::model::{helpers, Multiplicity, Visibility};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Operation<'input> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub visibility: Option<Visibility>,
+    #[serde(rename = "static", default, skip_serializing_if = "helpers::is_false")]
+    pub is_static: bool,
+    #[serde(rename = "abstract", default, skip_serializing_if = "helpers::is_false")]
+    pub is_abstract: bool,
+    #[serde(rename = "query", default, skip_serializing_if = "helpers::is_false")]
+    pub is_query: bool,
     pub name: &'input str,
     pub parameters: Vec<Parameter<'input>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub return_type: Option<Cow<'input, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raised_exceptions: Vec<Cow<'input, str>>,
+    /// property strings other than `query` and `abstract`, which are represented by flags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Cow<'input, str>>,
 }
 
 impl fmt::Display for Operation<'_> {
@@ -21,6 +32,12 @@ impl fmt::Display for Operation<'_> {
         if let Some(visibility) = self.visibility {
             write!(f, "{} ", visibility)?;
         }
+        if self.is_static {
+            write!(f, "{{static}} ")?;
+        }
+        if self.is_abstract {
+            write!(f, "{{abstract}} ")?;
+        }
         write!(f, "{}(", self.name)?;
         let mut parameters = self.parameters.iter();
         if let Some(x) = parameters.next() {
@@ -31,7 +48,23 @@ impl fmt::Display for Operation<'_> {
         }
         write!(f, ")")?;
         if let Some(return_type) = &self.return_type {
-            write!(f, ": {}", return_type)?;
+            write!(f, ": {}", helpers::NameOrQuoted(return_type))?;
+        }
+        let mut raised_exceptions = self.raised_exceptions.iter();
+        if let Some(x) = raised_exceptions.next() {
+            write!(f, " throws {}", helpers::NameOrQuoted(x))?;
+            for x in raised_exceptions {
+                write!(f, ", {}", helpers::NameOrQuoted(x))?;
+            }
+        }
+        let query = Some("query").filter(|_| self.is_query);
+        let mut properties = query.into_iter().chain(self.properties.iter().map(|x| x.as_ref()));
+        if let Some(x) = properties.next() {
+            write!(f, " {{{}", x)?;
+            for x in properties {
+                write!(f, ", {}", x)?;
+            }
+            write!(f, "}}")?;
         }
         Ok(())
     }
@@ -40,17 +73,54 @@ impl fmt::Display for Operation<'_> {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Parameter<'input> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<ParameterDirection>,
     pub name: &'input str,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#type: Option<Cow<'input, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplicity: Option<Multiplicity>,
+    /// the default value, as the expression was written in the source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Cow<'input, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Cow<'input, str>>,
 }
 
 impl fmt::Display for Parameter<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(direction) = self.direction {
+            write!(f, "{} ", direction)?;
+        }
         write!(f, "{}", self.name)?;
-        if let Some(r#type) = &self.r#type {
-            write!(f, ": {}", r#type)?;
+        helpers::fmt_typed_element(
+            f,
+            self.r#type.as_ref(),
+            self.multiplicity.as_ref(),
+            self.default.as_ref(),
+            &self.properties,
+        )
+    }
+}
+
+/// The [direction](https://www.uml-diagrams.org/operation.html#parameter) of a parameter.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParameterDirection {
+    In,
+    Out,
+    #[serde(rename = "inout")]
+    InOut,
+    Return,
+}
+
+impl fmt::Display for ParameterDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::In => write!(f, "in"),
+            Self::Out => write!(f, "out"),
+            Self::InOut => write!(f, "inout"),
+            Self::Return => write!(f, "return"),
         }
-        Ok(())
     }
 }
@@ -0,0 +1,31 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::helpers;
+
+/// A [template parameter](https://www.uml-diagrams.org/template.html) of a classifier, optionally
+/// with a bound and a default, as in `T: Comparable = Integer`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct TemplateParameter<'input> {
+    pub name: &'input str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bound: Option<Cow<'input, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Cow<'input, str>>,
+}
+
+impl fmt::Display for TemplateParameter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(bound) = &self.bound {
+            write!(f, ": {}", helpers::NameOrQuoted(bound))?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {}", helpers::NameOrQuoted(default))?;
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
-use super::Meta;
+use super::{helpers, Meta, MetaSpan};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(
@@ -11,25 +12,55 @@ use super::Meta;
     rename_all = "kebab-case"
 )]
 pub struct Edge<'input> {
-    #[serde(flatten)]
-    pub meta: BTreeMap<&'input str, Meta>,
-    pub a: &'input str,
-    pub b: &'input str,
+    #[serde(flatten, deserialize_with = "super::deserialize_metas")]
+    pub meta: BTreeMap<&'input str, Meta<'input>>,
+    pub a: Cow<'input, str>,
+    pub b: Cow<'input, str>,
     pub kind: EdgeKind<'input>,
+    #[serde(skip)]
+    pub span: MetaSpan,
 }
 
 impl fmt::Display for Edge<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut meta = self.meta.values();
-        if let Some(x) = meta.next() {
-            write!(f, "#[{}", x)?;
-            for x in meta {
-                write!(f, ", {}", x)?;
+        helpers::fmt_metas(f, &self.meta)?;
+
+        match &self.kind {
+            EdgeKind::Association {
+                name,
+                reading_direction,
+                a,
+                b,
+            } => {
+                if let Some(role) = &a.role {
+                    write!(f, "{}: ", role)?;
+                }
+                write!(f, "{}", self.a)?;
+                if let Some(multiplicity) = &a.multiplicity {
+                    write!(f, " {}", helpers::Quoted(multiplicity))?;
+                }
+                write!(f, " {} ", self.kind)?;
+                if let Some(multiplicity) = &b.multiplicity {
+                    write!(f, "{} ", helpers::Quoted(multiplicity))?;
+                }
+                if let Some(role) = &b.role {
+                    write!(f, "{}: ", role)?;
+                }
+                write!(f, "{}", self.b)?;
+                if let Some(name) = name {
+                    write!(f, " : ")?;
+                    if *reading_direction == Some(Direction::BToA) {
+                        write!(f, "< ")?;
+                    }
+                    write!(f, "{}", helpers::NameOrQuoted(name))?;
+                    if *reading_direction == Some(Direction::AToB) {
+                        write!(f, " >")?;
+                    }
+                }
+                Ok(())
             }
-            write!(f, "]\n")?;
+            _ => write!(f, "{} {} {}", self.a, self.kind, self.b),
         }
-
-        write!(f, "{} {} {}", self.a, self.kind, self.b)
     }
 }
 
@@ -50,11 +81,22 @@ pub enum EdgeKind<'input> {
     Dependency {
         direction: Direction,
         #[serde(skip_serializing_if = "Option::is_none")]
+        stereotype: Option<DependencyStereotype<'input>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
         name: Option<&'input str>,
     },
+    /// A [template binding](https://www.uml-diagrams.org/template.html), shown as `«bind»`
+    /// dependency from the bound element to the template
+    Binding {
+        direction: Direction,
+        substitutions: Vec<TemplateParameterSubstitution<'input>>,
+    },
     Association {
         #[serde(skip_serializing_if = "Option::is_none")]
-        name: Option<&'input str>,
+        name: Option<Cow<'input, str>>,
+        /// The direction in which the association's name should be read, if indicated
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reading_direction: Option<Direction>,
         a: AssociationEnd<'input>,
         b: AssociationEnd<'input>,
     },
@@ -70,21 +112,104 @@ impl fmt::Display for EdgeKind<'_> {
             Self::Generalization { direction: D::AToB } => write!(f, "--|>"),
             Self::Generalization { direction: D::BToA } => write!(f, "<|--"),
             Self::Dependency {
-                direction: D::AToB,
+                direction,
+                stereotype,
                 name,
-            } => write!(f, ".{}.>", name.unwrap_or_default()),
-            Self::Dependency {
-                direction: D::BToA,
-                name,
-            } => write!(f, "<.{}.", name.unwrap_or_default()),
-            Self::Association { name, a, b } => {
-                write!(f, "{:#}-{}-{}", a, name.unwrap_or_default(), b)
+            } => {
+                let label = match (stereotype, name) {
+                    (Some(stereotype), Some(name)) => format!("«{}» {}", stereotype, name),
+                    (Some(stereotype), None) => format!("«{}»", stereotype),
+                    (None, name) => name.unwrap_or_default().to_string(),
+                };
+                match direction {
+                    D::AToB => write!(f, ".{}.>", label),
+                    D::BToA => write!(f, "<.{}.", label),
+                }
+            }
+            Self::Binding {
+                direction,
+                substitutions,
+            } => {
+                let substitutions = substitutions
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match direction {
+                    D::AToB => write!(f, ".«bind» <{}>.>", substitutions),
+                    D::BToA => write!(f, "<.«bind» <{}>.", substitutions),
+                }
             }
+            Self::Association { a, b, .. } => write!(f, "{:#}--{}", a, b),
         }
     }
 }
 
+/// The substitution of a template parameter in a template binding, as in `T -> String`.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    bound(deserialize = "'de: 'input"),
+    rename_all = "kebab-case"
+)]
+pub struct TemplateParameterSubstitution<'input> {
+    pub formal: &'input str,
+    pub actual: Cow<'input, str>,
+}
+
+impl fmt::Display for TemplateParameterSubstitution<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.formal, helpers::NameOrQuoted(&self.actual))
+    }
+}
+
+/// The standard stereotypes of [dependencies](https://www.uml-diagrams.org/dependency.html),
+/// or a user-defined one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(
+    bound(deserialize = "'de: 'input"),
+    rename_all = "kebab-case"
+)]
+pub enum DependencyStereotype<'input> {
+    Use,
+    Create,
+    Call,
+    Instantiate,
+    Import,
+    #[serde(untagged)]
+    Other(&'input str),
+}
+
+impl<'input> DependencyStereotype<'input> {
+    pub fn from_name(name: &'input str) -> Self {
+        match name {
+            "use" => Self::Use,
+            "create" => Self::Create,
+            "call" => Self::Call,
+            "instantiate" => Self::Instantiate,
+            "import" => Self::Import,
+            name => Self::Other(name),
+        }
+    }
+
+    pub fn name(self) -> &'input str {
+        match self {
+            Self::Use => "use",
+            Self::Create => "create",
+            Self::Call => "call",
+            Self::Instantiate => "instantiate",
+            Self::Import => "import",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for DependencyStereotype<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum Direction {
     AToB,
@@ -101,7 +226,7 @@ pub struct AssociationEnd<'input> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<&'input str>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub multiplicity: Option<&'input str>,
+    pub multiplicity: Option<Cow<'input, str>>,
 }
 
 impl fmt::Display for AssociationEnd<'_> {
@@ -1,3 +1,112 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
 pub fn is_false(value: &bool) -> bool {
     !value
 }
+
+pub fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// the words the grammar reserves, which can't be used as names
+pub const KEYWORDS: &[&str] = &[
+    "abstract", "annotation", "as", "bend", "class", "dataType", "enumeration", "exception", "final", "in",
+    "inout", "interface", "note", "on", "out", "package", "pos", "primitive", "return", "static", "struct",
+    "throws", "via",
+];
+
+/// Whether a name can start with the character: `_` and characters with the `XID_Start` property,
+/// which are the grammar's `ID_Start` characters that are stable under normalization.
+pub fn is_name_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+/// Whether the character can appear in a name after the first one: `_`, `-` and characters with
+/// the `XID_Continue` property.
+pub fn is_name_continue(c: char) -> bool {
+    c == '_' || c == '-' || unicode_ident::is_xid_continue(c)
+}
+
+/// Whether the string can be written as a name, without quotes. Keywords can't.
+pub fn is_name(string: &str) -> bool {
+    let mut chars = string.chars();
+    chars.next().is_some_and(is_name_start) && chars.all(is_name_continue) && !KEYWORDS.contains(&string)
+}
+
+/// Displays a string literal, with quotes and escape sequences.
+pub struct Quoted<'a>(pub &'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// Displays a string as a name if possible, or as a string literal otherwise.
+pub struct NameOrQuoted<'a>(pub &'a str);
+
+impl fmt::Display for NameOrQuoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_name(self.0) {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "{}", Quoted(self.0))
+        }
+    }
+}
+
+/// Writes an element's metas as a `#[...]` block on its own line, or nothing if there are none.
+pub fn fmt_metas(f: &mut fmt::Formatter<'_>, meta: &BTreeMap<&str, super::Meta<'_>>) -> fmt::Result {
+    let mut meta = meta.values();
+    if let Some(x) = meta.next() {
+        write!(f, "#[{}", x)?;
+        for x in meta {
+            write!(f, ", {}", x)?;
+        }
+        writeln!(f, "]")?;
+    }
+    Ok(())
+}
+
+/// Writes a member's type, multiplicity, default value and property strings, as in
+/// `: Type[0..*] = [] {ordered, readOnly}`.
+pub fn fmt_typed_element(
+    f: &mut fmt::Formatter<'_>,
+    r#type: Option<&Cow<'_, str>>,
+    multiplicity: Option<&super::Multiplicity>,
+    default: Option<&Cow<'_, str>>,
+    properties: &[Cow<'_, str>],
+) -> fmt::Result {
+    if let Some(r#type) = r#type {
+        write!(f, ": {}", NameOrQuoted(r#type))?;
+    }
+    if let Some(multiplicity) = multiplicity {
+        write!(f, "{}", multiplicity)?;
+    }
+    if let Some(default) = default {
+        write!(f, " = {}", default)?;
+    }
+    let mut properties = properties.iter();
+    if let Some(x) = properties.next() {
+        write!(f, " {{{}", x)?;
+        for x in properties {
+            write!(f, ", {}", x)?;
+        }
+        write!(f, "}}")?;
+    }
+    Ok(())
+}
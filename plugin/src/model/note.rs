@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{helpers, AssociationEnd, Edge, EdgeKind, Meta, MetaSpan};
+
+/// A [comment](https://www.uml-diagrams.org/comment.html), shown as a note that is either
+/// free-standing or attached to classifiers, members or edges.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    bound(deserialize = "'de: 'input"),
+    rename_all = "kebab-case"
+)]
+pub struct Note<'input> {
+    #[serde(flatten, deserialize_with = "super::deserialize_metas")]
+    pub meta: BTreeMap<&'input str, Meta<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anchors: Vec<NoteAnchor<'input>>,
+    pub text: Cow<'input, str>,
+    #[serde(skip)]
+    pub span: MetaSpan,
+}
+
+impl fmt::Display for Note<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        helpers::fmt_metas(f, &self.meta)?;
+
+        write!(f, "note ")?;
+        let mut anchors = self.anchors.iter();
+        if let Some(x) = anchors.next() {
+            write!(f, "on {}", x)?;
+            for x in anchors {
+                write!(f, ", {}", x)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}", helpers::Quoted(&self.text))
+    }
+}
+
+/// The element a note is attached to. When parsing, a reference such as `Order::total` is
+/// ambiguous: it can be a classifier in a package, or a member of a classifier. It is parsed as a
+/// classifier and turned into a member when references are resolved.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    bound(deserialize = "'de: 'input"),
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum NoteAnchor<'input> {
+    Classifier {
+        id: Cow<'input, str>,
+    },
+    Member {
+        classifier: Cow<'input, str>,
+        member: Cow<'input, str>,
+    },
+    /// the edge(s) connecting `a` and `b`. Written with a plain `--`, any edge regardless of kind
+    /// and direction; written with a mark such as `*-->`, only the associations with these ends
+    Edge {
+        a: Cow<'input, str>,
+        b: Cow<'input, str>,
+        #[serde(default, skip_serializing_if = "helpers::is_default")]
+        a_end: AssociationEnd<'input>,
+        #[serde(default, skip_serializing_if = "helpers::is_default")]
+        b_end: AssociationEnd<'input>,
+    },
+}
+
+impl NoteAnchor<'_> {
+    /// Whether an edge is one the anchor refers to; never true for classifier and member anchors.
+    pub fn is_on_edge(&self, edge: &Edge<'_>) -> bool {
+        let Self::Edge { a, b, a_end, b_end } = self else {
+            return false;
+        };
+        let same_mark = |x: &AssociationEnd<'_>, y: &AssociationEnd<'_>| {
+            x.aggregation == y.aggregation && x.navigable == y.navigable
+        };
+        if *a_end == AssociationEnd::default() && *b_end == AssociationEnd::default() {
+            return (edge.a == *a && edge.b == *b) || (edge.a == *b && edge.b == *a);
+        }
+        match &edge.kind {
+            EdgeKind::Association { a: edge_a_end, b: edge_b_end, .. } => {
+                (edge.a == *a && edge.b == *b && same_mark(edge_a_end, a_end) && same_mark(edge_b_end, b_end))
+                    || (edge.a == *b && edge.b == *a && same_mark(edge_a_end, b_end) && same_mark(edge_b_end, a_end))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for NoteAnchor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Classifier { id } => write!(f, "{}", id),
+            Self::Member { classifier, member } => write!(f, "{}::{}", classifier, member),
+            Self::Edge { a, b, a_end, b_end } => write!(f, "{} {:#}--{} {}", a, a_end, b_end, b),
+        }
+    }
+}
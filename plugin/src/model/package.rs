@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{helpers, Classifier, Edge, Meta, Note};
+
+/// A [package](https://www.uml-diagrams.org/package.html) grouping classifiers, edges and nested
+/// packages. Members of a package are referred to from outside by their qualified name, e.g.
+/// `billing::Invoice`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    bound(deserialize = "'de: 'input"),
+    rename_all = "kebab-case"
+)]
+pub struct Package<'input> {
+    #[serde(flatten, deserialize_with = "super::deserialize_metas")]
+    pub meta: BTreeMap<&'input str, Meta<'input>>,
+    pub name: &'input str,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub classifiers: Vec<Classifier<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edges: Vec<Edge<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note<'input>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<Package<'input>>,
+}
+
+impl fmt::Display for Package<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        helpers::fmt_metas(f, &self.meta)?;
+
+        write!(f, "package {} {{", self.name)?;
+        let classifiers = self.classifiers.iter().map(|x| x.to_string());
+        let packages = self.packages.iter().map(|x| x.to_string());
+        let edges = self.edges.iter().map(|x| x.to_string());
+        let notes = self.notes.iter().map(|x| x.to_string());
+        for item in classifiers.chain(packages).chain(edges).chain(notes) {
+            for line in item.lines() {
+                write!(f, "\n  {}", line)?;
+            }
+        }
+        write!(f, "\n}}")
+    }
+}
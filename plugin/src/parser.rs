@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 use std::f32::consts::PI;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use lalrpop_util::lexer::Token;
 use lalrpop_util::ParseError;
@@ -13,15 +15,261 @@ lalrpop_util::lalrpop_mod!(
     "/grammar.rs"
 );
 
-pub type Error<'a> = ParseError<usize, Token<'a>, &'static str>;
+pub type Error<'a> = ParseError<usize, Token<'a>, UserError>;
 pub type Result<'a, T> = std::result::Result<T, Error<'a>>;
 
-pub fn parse(source: &str) -> Result<model::Diagram<'_>> {
-    let parser = grammar::DiagramParser::new();
-    parser.parse(source)
+/// An error found by a grammar action, e.g. a number that is out of range, along with the
+/// byte range of the input that caused it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserError {
+    pub start: usize,
+    pub end: usize,
+    pub message: &'static str,
 }
 
-fn from_mark(mark: &str) -> model::AssociationEnd {
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The result of a helper called from a grammar action; the action adds the location via [at].
+type ActionResult<T> = std::result::Result<T, &'static str>;
+
+fn at<'a>(start: usize, end: usize) -> impl Fn(&'static str) -> Error<'a> {
+    move |message| ParseError::User { error: UserError { start, end, message } }
+}
+
+pub fn parse(source: &str) -> Result<'_, model::Diagram<'_>> {
+    // building the parser compiles the lexer's regexes, so only do that once
+    static PARSER: OnceLock<grammar::DiagramParser> = OnceLock::new();
+    let parser = PARSER.get_or_init(grammar::DiagramParser::new);
+    parser.parse(source, source)
+}
+
+/// The items that can appear in a diagram or package.
+enum Item<'input> {
+    Classifier(model::Classifier<'input>),
+    Edge(model::Edge<'input>),
+    Note(model::Note<'input>),
+    Package(model::Package<'input>),
+}
+
+type Items<'input> = (
+    Vec<model::Classifier<'input>>,
+    Vec<model::Edge<'input>>,
+    Vec<model::Note<'input>>,
+    Vec<model::Package<'input>>,
+);
+
+fn split_items(items: Vec<Item<'_>>) -> Items<'_> {
+    let mut classifiers = Vec::new();
+    let mut edges = Vec::new();
+    let mut notes = Vec::new();
+    let mut packages = Vec::new();
+    for item in items {
+        match item {
+            Item::Classifier(item) => classifiers.push(item),
+            Item::Edge(item) => edges.push(item),
+            Item::Note(item) => notes.push(item),
+            Item::Package(item) => packages.push(item),
+        }
+    }
+    (classifiers, edges, notes, packages)
+}
+
+/// The things that can precede a classifier's kind. These can appear in any order.
+enum ClassifierModifier<'input> {
+    Abstract,
+    Final,
+    Stereotypes(Vec<&'input str>),
+}
+
+/// The things that can precede a member's name. These can appear in any order.
+enum MemberModifier {
+    Visibility(model::Visibility),
+    Static,
+    Abstract,
+}
+
+#[derive(Default)]
+struct MemberModifiers {
+    visibility: Option<model::Visibility>,
+    is_static: bool,
+    is_abstract: bool,
+}
+
+impl MemberModifiers {
+    fn from_modifiers(modifiers: Vec<MemberModifier>) -> ActionResult<Self> {
+        let mut result = Self::default();
+        for modifier in modifiers {
+            match modifier {
+                MemberModifier::Visibility(_) if result.visibility.is_some() => {
+                    return Err("a member can only have one visibility");
+                }
+                MemberModifier::Visibility(visibility) => result.visibility = Some(visibility),
+                MemberModifier::Static => result.is_static = true,
+                MemberModifier::Abstract => result.is_abstract = true,
+            }
+        }
+        Ok(result)
+    }
+
+    fn from_attribute_modifiers(modifiers: Vec<MemberModifier>) -> ActionResult<Self> {
+        let result = Self::from_modifiers(modifiers)?;
+        if result.is_abstract {
+            return Err("attributes can't be abstract");
+        }
+        Ok(result)
+    }
+}
+
+/// The parts shared by attributes and parameters.
+struct TypedElement<'input> {
+    name: &'input str,
+    r#type: Option<Cow<'input, str>>,
+    multiplicity: Option<model::Multiplicity>,
+    default: Option<Cow<'input, str>>,
+    properties: Vec<Cow<'input, str>>,
+}
+
+/// An operation's parts following its modifiers.
+struct OperationSignature<'input> {
+    name: &'input str,
+    parameters: Vec<model::Parameter<'input>>,
+    return_type: Option<Cow<'input, str>>,
+    raised_exceptions: Vec<Cow<'input, str>>,
+    properties: Vec<Cow<'input, str>>,
+}
+
+fn operation<'input>(
+    modifiers: MemberModifiers,
+    signature: OperationSignature<'input>,
+) -> model::Operation<'input> {
+    let MemberModifiers { visibility, is_static, mut is_abstract } = modifiers;
+    let OperationSignature { name, parameters, return_type, raised_exceptions, properties } = signature;
+    let mut is_query = false;
+    let properties = properties
+        .into_iter()
+        .filter(|property| match property.as_ref() {
+            "query" => {
+                is_query = true;
+                false
+            }
+            "abstract" => {
+                is_abstract = true;
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    model::Operation {
+        visibility,
+        is_static,
+        is_abstract,
+        is_query,
+        name,
+        parameters,
+        return_type,
+        raised_exceptions,
+        properties,
+    }
+}
+
+fn check_binding_stereotype(stereotype: &str) -> ActionResult<()> {
+    if stereotype != "bind" {
+        return Err("template parameter substitutions require «bind»");
+    }
+    Ok(())
+}
+
+/// Parses the metas that are not keywords: relative positions such as `right-of(A)`, `below(A, 2)`
+/// and `align-x(A, B)`, as well as `route(...)`, `from(...)` and `to(...)`.
+fn named_meta<'input>(
+    name: &str,
+    references: Vec<Cow<'input, str>>,
+    distance: Option<f32>,
+) -> ActionResult<model::Meta<'input>> {
+    match name {
+        "route" => {
+            let routing = match (references.as_slice(), distance) {
+                ([routing], None) if routing == "auto" => model::Routing::Auto,
+                ([routing], None) if routing == "orthogonal" => model::Routing::Orthogonal,
+                ([routing], None) if routing == "straight" => model::Routing::Straight,
+                _ => return Err("expected `route(auto)`, `route(orthogonal)` or `route(straight)`"),
+            };
+            Ok(model::Meta::Route(routing))
+        }
+        "from" | "to" => {
+            let side = match (references.as_slice(), distance) {
+                ([side], None) => model::Side::from_name(side),
+                _ => None,
+            };
+            let side = side.ok_or("expected a side: `north`, `east`, `south` or `west`")?;
+            Ok(if name == "from" { model::Meta::From(side, None) } else { model::Meta::To(side, None) })
+        }
+        "right-of" | "left-of" | "above" | "below" => {
+            let [reference]: [_; 1] = references.try_into().map_err(|_| "relative positions refer to exactly one classifier")?;
+            let distance = distance.unwrap_or(1.0);
+            Ok(match name {
+                "right-of" => model::Meta::RightOf(reference, distance),
+                "left-of" => model::Meta::LeftOf(reference, distance),
+                "above" => model::Meta::Above(reference, distance),
+                _ => model::Meta::Below(reference, distance),
+            })
+        }
+        "align-x" | "align-y" if distance.is_some() => Err("alignments don't have a distance"),
+        "align-x" => Ok(model::Meta::AlignX(references)),
+        "align-y" => Ok(model::Meta::AlignY(references)),
+        _ => Err(UNKNOWN_META),
+    }
+}
+
+/// Parses the metas that are not keywords and take an angle, i.e. `loop(...)`.
+fn angle_meta<'input>(name: &str, angle: f32) -> ActionResult<model::Meta<'input>> {
+    match name {
+        "loop" => Ok(model::Meta::Loop(angle)),
+        _ => Err(UNKNOWN_META),
+    }
+}
+
+const UNKNOWN_META: &str = "unknown meta; expected `pos`, `via`, `bend`, `right-of`, `left-of`, `above`, `below`, \
+    `align-x`, `align-y`, `route`, `from`, `to` or `loop`";
+
+/// Makes a `via` meta, which only needs resolving if some points are relative to classifiers.
+fn via(points: Vec<model::Anchor<'_>>) -> model::Meta<'_> {
+    if points.iter().all(|point| point.reference.is_none()) {
+        model::Meta::Via(points.into_iter().map(|point| point.offset).collect())
+    } else {
+        model::Meta::AnchoredVia(points, Vec::new())
+    }
+}
+
+fn anchor<'input>(
+    reference: Cow<'input, str>,
+    side: Option<&str>,
+    offset: Option<(f32, f32)>,
+) -> ActionResult<model::Anchor<'input>> {
+    let side = match side {
+        Some(side) => Some(model::Side::from_name(side).ok_or("expected a side: `north`, `east`, `south` or `west`")?),
+        None => None,
+    };
+    let offset = offset.unwrap_or((0.0, 0.0));
+    Ok(model::Anchor { reference: Some(reference), side, offset })
+}
+
+/// Splits an association mark such as `<--o` into its two ends.
+fn split_mark<'input>(
+    mark: &str,
+) -> ActionResult<(model::AssociationEnd<'input>, model::AssociationEnd<'input>)> {
+    let (a, b) = mark.split_once("--").ok_or("association mark must contain `--`")?;
+    if b.contains("--") {
+        return Err("association mark must contain `--` only once");
+    }
+    Ok((from_mark(a), from_mark(b)))
+}
+
+fn from_mark<'input>(mark: &str) -> model::AssociationEnd<'input> {
     let mut end = model::AssociationEnd::default();
     if mark.contains("<") || mark.contains(">") {
         end.navigable = Some(true);
@@ -38,31 +286,78 @@ fn from_mark(mark: &str) -> model::AssociationEnd {
     end
 }
 
-fn parse_isize(number: &str) -> Result<'_, isize> {
-    isize::from_str(number).map_err(|_| ParseError::User { error: "number is too big" })
+fn parse_isize(number: &str) -> ActionResult<isize> {
+    isize::from_str(number).map_err(|_| "number is too big")
 }
 
-fn parse_f32(number: &str) -> Result<'_, f32> {
-    match f32::from_str(number).expect("value should have conformed to the format") {
-        num if num.is_finite() => Ok(num),
-        _ => Err(ParseError::User { error: "number is too big" }),
+fn parse_usize(number: &str) -> ActionResult<usize> {
+    usize::from_str(number).map_err(|_| "number is not a valid count")
+}
+
+fn parse_f32(number: &str) -> ActionResult<f32> {
+    match f32::from_str(number) {
+        Ok(num) if num.is_finite() => Ok(num),
+        Err(_) => Err("invalid number"),
+        _ => Err("number is too big"),
     }
 }
 
-fn parse_angle(angle: &str) -> Result<'_, f32> {
-    let (number, unit) = angle.split_at(angle.len() - 3);
-    let number = parse_f32(number)?;
-    let factor = match unit {
-        "rad" => 1.0,
-        "deg" => PI / 180.0,
-        _ => panic!("angular unit should have been 'rad' or 'deg'"),
-    };
-    Ok(number * factor)
+fn parse_angle(angle: &str) -> ActionResult<f32> {
+    if let Some(number) = angle.strip_suffix("rad") {
+        Ok(parse_f32(number)?)
+    } else if let Some(number) = angle.strip_suffix("deg") {
+        Ok(parse_f32(number)? * PI / 180.0)
+    } else {
+        Err("angular unit must be 'rad' or 'deg'")
+    }
+}
+
+fn strip_colon(name: &str) -> &str {
+    name.strip_suffix(':').unwrap_or(name)
 }
 
-fn parse_string(string: &str) -> Cow<'_, str> {
-    // TODO process escape sequences
-    Cow::from(&string[1..string.len() - 1])
+fn parse_string(string: &str) -> ActionResult<Cow<'_, str>> {
+    let string = string
+        .strip_prefix('"')
+        .and_then(|string| string.strip_suffix('"'))
+        .ok_or("string must be enclosed in quotes")?;
+    if !string.contains('\\') {
+        return Ok(Cow::from(string));
+    }
+
+    let mut result = String::with_capacity(string.len());
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        let c = match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .map(|(code, _)| code)
+                    .ok_or("unicode escape sequence must have the form \\u{...}")?;
+                let c = u32::from_str_radix(code, 16)
+                    .ok()
+                    .filter(|_| (1..=6).contains(&code.len()))
+                    .and_then(char::from_u32)
+                    .ok_or("invalid unicode escape sequence")?;
+                chars = rest[code.len() + 2..].chars();
+                c
+            }
+            _ => return Err("invalid escape sequence"),
+        };
+        result.push(c);
+    }
+    Ok(Cow::Owned(result))
 }
 
 #[cfg(test)]
@@ -72,6 +367,9 @@ mod tests {
     pub fn test_parse(input: &str, expected: &str) {
         let actual = parse(input).unwrap();
         assert_eq!(format!("{}", actual), expected);
+        // the displayed diagram is valid source for the same diagram
+        let reparsed = parse(expected).unwrap_or_else(|error| panic!("{expected:?} doesn't parse: {error:?}"));
+        assert_eq!(format!("{}", reparsed), expected);
     }
 
     #[test]
@@ -82,6 +380,12 @@ mod tests {
         test_parse("final class A", "final class A");
         test_parse("exception A", "«exception» class A");
         test_parse("annotation A", "«annotation» interface A");
+        test_parse("«exception» class A", "«exception» class A");
+        test_parse("«entity» «aggregate-root» class A", "«entity, aggregate-root» class A");
+        test_parse("<<entity>> <<aggregate-root>> class A", "«entity, aggregate-root» class A");
+        test_parse("«entity, aggregate-root» class A", "«entity, aggregate-root» class A");
+        test_parse("«entity» abstract class A", "abstract «entity» class A");
+        test_parse("final «entity» struct A", "final «entity, struct» class A");
 
         test_parse("class A\n\nclass B", "class A\nclass B");
 
@@ -90,6 +394,82 @@ mod tests {
         test_parse("#[pos(0, 0)]\n\nclass A", "#[pos(0, 0)]\nclass A");
     }
 
+    #[test]
+    fn test_parse_packages() {
+        test_parse("package a {}", "package a {\n}");
+        test_parse(
+            r#"
+            class Customer
+            package billing {
+                #[pos(0, 0)]
+                class Invoice
+
+                package tax {
+                    class Rate
+                }
+                Invoice --> tax::Rate
+            }
+            Customer -- billing::Invoice
+            "#,
+            "class Customer\npackage billing {\n  #[pos(0, 0)]\n  class Invoice\n  package tax {\n    class Rate\n  }\n  \
+             Invoice --> tax::Rate\n}\nCustomer -- billing::Invoice",
+        );
+    }
+
+    #[test]
+    fn test_parse_notes() {
+        test_parse("#[pos(1, 0)] note \"foo\"", "#[pos(1, 0)]\nnote \"foo\"");
+        test_parse("note on A \"foo\"", "note on A \"foo\"");
+        test_parse("note on A::x, p::B \"foo\"", "note on A::x, p::B \"foo\"");
+        test_parse("note on A *--> B \"foo\"", "note on A *--> B \"foo\"");
+        test_parse("note on A <--x B, A -- B \"foo\"", "note on A <--x B, A -- B \"foo\"");
+        test_parse("package p {\n  note \"foo\"\n}", "package p {\n  note \"foo\"\n}");
+    }
+
+    #[test]
+    fn test_parse_templates() {
+        test_parse("class List<T>", "class List<T>");
+        test_parse("interface Map<K, V> as M", "interface Map<K, V> as M");
+        test_parse(
+            "class SortedList<T: \"Comparable<T>\" = Integer, U = V>",
+            "class SortedList<T: \"Comparable<T>\" = Integer, U = V>",
+        );
+        test_parse("enumeration E<T> { A }", "enumeration E<T> {\n  A\n}");
+        test_parse("StringList .«bind» <T -> String>.> List", "StringList .«bind» <T -> String>.> List");
+        test_parse(
+            "Map <.<<bind>> <K -> Int, V -> \"List<Int>\">. IntMap",
+            "Map <.«bind» <K -> Int, V -> \"List<Int>\">. IntMap",
+        );
+        assert!(parse("A .«use» <T -> String>.> B").is_err());
+    }
+
+    #[test]
+    fn test_parse_strings() {
+        let note = |source| parse(source).unwrap().notes[0].text.clone();
+        assert_eq!(note(r#"note "foo""#), "foo");
+        assert!(matches!(note(r#"note "foo""#), Cow::Borrowed(_)));
+        assert_eq!(note(r#"note "a \"b\" \\ c""#), r#"a "b" \ c"#);
+        assert_eq!(note(r#"note "a\nb\tc\r""#), "a\nb\tc\r");
+        assert_eq!(note(r#"note "\u{48}\u{1F600}!""#), "H\u{1F600}!");
+        assert!(parse(r#"note "\x""#).is_err());
+        assert!(parse(r#"note "\u{110000}""#).is_err());
+        assert!(parse(r#"note "\u{}""#).is_err());
+        assert!(parse(r#"note "\u48""#).is_err());
+
+        test_parse(r#"note "a \"b\"\n\\""#, r#"note "a \"b\"\n\\""#);
+        test_parse(r#"A "0..\"*\"" -- B"#, r#"A "0..\"*\"" -- B"#);
+        test_parse("class A {\n  x: \"Map<String, \\\"x\\\">\"\n}", "class A {\n  x: \"Map<String, \\\"x\\\">\"\n}");
+    }
+
+    #[test]
+    fn test_parse_quoted_names() {
+        test_parse(r#"class "Order Item" as OI"#, r#"class "Order Item" as OI"#);
+        test_parse(r#"class "Order" as O"#, r#"class Order as O"#);
+        test_parse(r#"class "interface""#, r#"class "interface""#);
+        test_parse(r#"class "café""#, "class café");
+        test_parse(r#"class "a²""#, r#"class "a²""#);
+    }
+
     #[test]
     fn test_parse_class_body() {
         test_parse("class A", "class A");
@@ -114,7 +494,93 @@ mod tests {
                 + op( x, )
                 + op(x:  X , y: Y): Z
             }"#,
-            "class A {\n  - attr\n  + attr2: Baz<T>\n  + op(x)\n  + op(x: X, y: Y): Z\n}",
+            "class A {\n  - attr\n  + attr2: \"Baz<T>\"\n  + op(x)\n  + op(x: X, y: Y): Z\n}",
+        );
+    }
+
+    #[test]
+    fn test_parse_typed_elements() {
+        test_parse(
+            "class A {\n  items: Item[0..*] = [] {ordered, readOnly}\n}",
+            "class A {\n  items: Item[0..*] = [] {ordered, readOnly}\n}",
+        );
+        test_parse(
+            r#"
+            class A {
+                - a: Int[1] = 1
+                - b[*]
+                - c: "List<T>"[2..5] = of( 1 ,2 ) {redefines x}
+                - d = "foo"
+                + op(x: Int[0..1] = -1, y: Y {unique})
+            }"#,
+            "class A {\n  - a: Int[1] = 1\n  - b[*]\n  - c: \"List<T>\"[2..5] = of( 1 ,2 ) {redefines x}\n  \
+             - d = \"foo\"\n  + op(x: Int[0..1] = -1, y: Y {unique})\n}",
+        );
+        test_parse("class A {\n  b[0..*]\n}", "class A {\n  b[0..*]\n}");
+        test_parse("class A {\n  x: \"static\"\n  y: Größe\n}", "class A {\n  x: \"static\"\n  y: Größe\n}");
+        assert!(parse("class A {\n  a[2..1]\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_operations() {
+        test_parse(
+            r#"
+            class A {
+                + op(in x: Int, out y: Int[*], inout z = 1, return r: Bool)
+                + get(): Int {query}
+                + read() throws IOException, "Error<T>"
+                + run(): Int throws E {abstract, query, sequential}
+            }"#,
+            "class A {\n  + op(in x: Int, out y: Int[*], inout z = 1, return r: Bool)\n  \
+             + get(): Int {query}\n  + read() throws IOException, \"Error<T>\"\n  \
+             + {abstract} run(): Int throws E {query, sequential}\n}",
+        );
+    }
+
+    #[test]
+    fn test_parse_member_modifiers() {
+        test_parse(
+            r#"
+            abstract class A {
+                + {static} count: Int
+                static - instances
+                # /age: Int
+                /name
+                + {abstract} op()
+                abstract op2(): Int
+                {static} + {abstract} op3()
+            }"#,
+            "abstract class A {\n  + {static} count: Int\n  - {static} instances\n  # /age: Int\n  /name\n  \
+             + {abstract} op()\n  {abstract} op2(): Int\n  + {static} {abstract} op3()\n}",
+        );
+        test_parse(
+            "enumeration E {\n  A\n  {static} x: E\n  /y\n  static values(): \"List<E>\"\n}",
+            "enumeration E {\n  A\n  {static} x: E\n  /y\n  {static} values(): \"List<E>\"\n}",
+        );
+        assert!(parse("class A {\n  + - attr\n}").is_err());
+        assert!(parse("class A {\n  {abstract} attr\n}").is_err());
+    }
+
+    #[test]
+    fn test_parse_enumeration_body() {
+        test_parse("enumeration Color", "enumeration Color");
+        test_parse("enumeration Color {}", "enumeration Color");
+        test_parse("enumeration Color { RED; GREEN }", "enumeration Color {\n  RED\n  GREEN\n}");
+        test_parse("enumeration Color { RED, GREEN, }", "enumeration Color {\n  RED\n  GREEN\n}");
+        test_parse(
+            r##"
+            enumeration Color {
+                RED("#f00") = 1
+                GREEN(0, 255, 0) = 2;
+                BLUE()
+                + hex: String
+                + mix(other: Color): Color
+            }"##,
+            "enumeration Color {\n  RED(\"#f00\") = 1\n  GREEN(0, 255, 0) = 2\n  BLUE()\n  + hex: String\n  + mix(other: Color): Color\n}",
+        );
+        test_parse(
+            "enumeration Color {\n  RED\n  + mix(other: Color): Color\n}",
+            "enumeration Color {\n  RED\n  + mix(other: Color): Color\n}",
         );
     }
 
@@ -128,10 +594,48 @@ mod tests {
         test_parse("A  --|> B", "A --|> B");
         test_parse("A  <|.. B", "A <|.. B");
         test_parse("A  <.. B", "A <.. B");
+        test_parse("A .use.> B", "A .use.> B");
+        test_parse("A <.use. B", "A <.use. B");
+        test_parse("A .«use».> B", "A .«use».> B");
+        test_parse("A <.<<create>> factory. B", "A <.«create» factory. B");
+        test_parse("A .«trace».> B", "A .«trace».> B");
+
+        test_parse("A \"1\" o-- \"0..*\" B", "A \"1\" o-- \"0..*\" B");
+        test_parse("a: A -- b: B", "a: A -- b: B");
+        test_parse("A -- B : foo", "A -- B : foo");
+        test_parse("A -- B : \"works for\"", "A -- B : \"works for\"");
+        test_parse("A -- B : foo >", "A -- B : foo >");
+        test_parse("A -- B : ◀ foo", "A -- B : < foo");
+        test_parse(
+            "Order \"1\" o-- \"0..*\" items: LineItem : contains",
+            "Order \"1\" o-- \"0..*\" items: LineItem : contains",
+        );
+        test_parse("order:Order *--x \"*\" items:LineItem : contains", "order: Order *--x \"*\" items: LineItem : contains");
 
         test_parse("#[via((0, 0))] A  -- B", "#[via((0, 0))]\nA -- B");
         test_parse("#[via((0, 0), (1, 0))] A  -- B", "#[via((0, 0), (1, 0))]\nA -- B");
         test_parse("#[bend(-15deg)] A  -- B", "#[bend(-15deg)]\nA -- B");
-        test_parse("#[via((0, 0)), bend(0.3rad)] A  -- B", "#[bend(17.188734deg), via((0, 0))]\nA -- B");
+        test_parse("#[via((0, 0)), bend(0.3rad)] A  -- B", "#[bend(0.3rad), via((0, 0))]\nA -- B");
+        assert!(parse("#[bend(1000000000000000000000000000000000000000deg)] A -- B").is_err());
+
+        test_parse("#[right-of(A)] class B", "#[right-of(A)]\nclass B");
+        test_parse("#[below(p::A, 2), align-x(C, D)] class B", "#[align-x(C, D), below(p::A, 2)]\nclass B");
+        test_parse("#[left-of(A, 1.5), above(A, 1)] note \"a\"", "#[above(A), left-of(A, 1.5)]\nnote \"a\"");
+        assert!(parse("#[right-of(A, B)] class C").is_err());
+        assert!(parse("#[align-y(A, 2)] class C").is_err());
+        assert!(parse("#[next-to(A)] class C").is_err());
+        test_parse("#[route(orthogonal)] A -- B", "#[route(orthogonal)]\nA -- B");
+        assert!(parse("#[route(diagonal)] A -- B").is_err());
+        test_parse("#[loop(90deg), bend(120deg)] A -- A", "#[bend(120deg), loop(90deg)]\nA -- A");
+        assert!(parse("#[turn(90deg)] A -- A").is_err());
+        test_parse(
+            "#[via(A.east + (0.5, 0), (1, 1), p::B, B.north)] A -- B",
+            "#[via(A.east + (0.5, 0), (1, 1), p::B, B.north)]\nA -- B",
+        );
+        test_parse("#[from(south), to(north)] A -- B", "#[from(south), to(north)]\nA -- B");
+        assert!(parse("#[via(A.up)] A -- B").is_err());
+        assert!(parse("#[from(A)] A -- B").is_err());
+        test_parse("class below {\n  above: right-of\n}", "class below {\n  above: right-of\n}");
+        assert!(parse("#[pos(0, 1000000000000000000000000000000000000000.0)] class A").is_err());
     }
 }
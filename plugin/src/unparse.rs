@@ -0,0 +1,215 @@
+use ciborium::Value;
+use serde::de::value::{BorrowedStrDeserializer, Error, MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{self, Deserialize, Deserializer, IntoDeserializer, Visitor};
+
+use crate::diagnostic::Diagnostic;
+use crate::model::Diagram;
+use crate::parser;
+
+/// Turns a diagram, as returned by [crate::parse] or built from data, back into source. Parsing
+/// the source results in the same diagram again; diagrams that can't be written as valid source,
+/// e.g. because of a name with spaces where only plain names are allowed, or that would be parsed
+/// differently, e.g. a `[*]` multiplicity with other bounds, are an error.
+pub fn unparse(diagram: &Value) -> Result<String, Error> {
+    let mut diagram = Diagram::deserialize(ValueDeserializer(diagram))?;
+    let source = diagram.to_string();
+    let mut reparsed = parser::parse(&source).map_err(|error| {
+        let diagnostic = Diagnostic::from_parse_error(&source, &error);
+        de::Error::custom(format_args!("the diagram is not valid source\n{}", diagnostic.render(&source)))
+    })?;
+    // compare the diagrams as `parse` returns them: with resolved references, and without where
+    // elements are in the source
+    diagram.resolve_references();
+    reparsed.resolve_references();
+    let value = |diagram: &Diagram<'_>| Value::serialized(diagram).map_err(de::Error::custom);
+    if value(&reparsed)? != value(&diagram)? {
+        return Err(de::Error::custom(format_args!("the diagram can't be written as source; it would change to\n{}", source)));
+    }
+    Ok(source)
+}
+
+/// Deserializes from a CBOR value, lending out its strings. The model borrows names from the
+/// source, which deserializing with [ciborium::from_reader] doesn't support.
+struct ValueDeserializer<'de>(&'de Value);
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Integer(integer) => {
+                let integer = i128::from(*integer);
+                match (i64::try_from(integer), u64::try_from(integer)) {
+                    (Ok(integer), _) => visitor.visit_i64(integer),
+                    (_, Ok(integer)) => visitor.visit_u64(integer),
+                    _ => Err(de::Error::custom("integer out of range")),
+                }
+            },
+            Value::Float(float) => visitor.visit_f64(*float),
+            Value::Text(text) => visitor.visit_borrowed_str(text),
+            Value::Bytes(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Value::Bool(bool) => visitor.visit_bool(*bool),
+            Value::Null => visitor.visit_unit(),
+            Value::Tag(_, value) => ValueDeserializer(value).deserialize_any(visitor),
+            Value::Array(values) => {
+                let mut seq = SeqDeserializer::new(values.iter().map(ValueDeserializer));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            Value::Map(entries) => {
+                let mut map = MapDeserializer::new(entries.iter().map(|(k, v)| (ValueDeserializer(k), ValueDeserializer(v))));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+            _ => Err(de::Error::custom("unsupported CBOR value")),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Enums are either a variant's name, or a map from the name to the variant's content.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.0 {
+            Value::Text(text) => visitor.visit_enum(BorrowedStrDeserializer::new(text)),
+            Value::Map(entries) if entries.len() == 1 => {
+                let entries = entries.iter().map(|(k, v)| (ValueDeserializer(k), ValueDeserializer(v)));
+                visitor.visit_enum(MapAccessDeserializer::new(MapDeserializer::new(entries)))
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cbor_encode;
+
+    fn test_unparse(source: &str, expected: &str) {
+        let mut diagram = parser::parse(source).unwrap();
+        diagram.resolve_references();
+        let value: Value = ciborium::from_reader(cbor_encode(&diagram).unwrap().as_slice()).unwrap();
+        assert_eq!(unparse(&value).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_unparse_metas() {
+        test_unparse(
+            "#[bend(30deg), loop(0.3rad)] A -- A",
+            "#[bend(30deg), loop(0.3rad)]\nA -- A",
+        );
+        test_unparse(
+            "#[from(south), to(north), via(A.east + (1, 0), (2, 2))] A -- B",
+            "#[from(south), to(north), via(A.east + (1, 0), (2, 2))]\nA -- B",
+        );
+        test_unparse("#[below(A, 2), route(straight)] class B", "#[below(A, 2), route(straight)]\nclass B");
+    }
+
+    #[test]
+    fn test_unparse_dictionary() {
+        // as built in Typst, with optional fields left out
+        let diagram = Value::Map(vec![(
+            Value::Text("classifiers".into()),
+            Value::Array(vec![Value::Map(vec![
+                (Value::Text("kind".into()), Value::Text("class".into())),
+                (Value::Text("name".into()), Value::Text("Order Item".into())),
+                (Value::Text("pos".into()), Value::Array(vec![Value::Integer(1.into()), Value::Float(0.5)])),
+                (
+                    Value::Text("attributes".into()),
+                    Value::Array(vec![Value::Map(vec![
+                        (Value::Text("visibility".into()), Value::Text("+".into())),
+                        (Value::Text("name".into()), Value::Text("id".into())),
+                    ])]),
+                ),
+            ])]),
+        )]);
+        assert_eq!(unparse(&diagram).unwrap(), "#[pos(1, 0.5)]\nclass \"Order Item\" {\n  + id\n}");
+
+        let diagram = Value::Map(vec![(
+            Value::Text("classifiers".into()),
+            Value::Array(vec![Value::Map(vec![
+                (Value::Text("kind".into()), Value::Text("class".into())),
+                (Value::Text("name".into()), Value::Text("A".into())),
+                (Value::Text("size".into()), Value::Integer(1.into())),
+            ])]),
+        )]);
+        assert!(unparse(&diagram).unwrap_err().to_string().contains("unknown meta `size`"));
+
+        // attribute names are never quoted
+        let diagram = Value::Map(vec![(
+            Value::Text("classifiers".into()),
+            Value::Array(vec![Value::Map(vec![
+                (Value::Text("kind".into()), Value::Text("class".into())),
+                (Value::Text("name".into()), Value::Text("A".into())),
+                (
+                    Value::Text("attributes".into()),
+                    Value::Array(vec![Value::Map(vec![(Value::Text("name".into()), Value::Text("order id".into()))])]),
+                ),
+            ])]),
+        )]);
+        let error = unparse(&diagram).unwrap_err().to_string();
+        assert!(error.starts_with("the diagram is not valid source\nerror: "), "{error}");
+        assert!(error.contains("order id"), "{error}");
+
+        // `[*]` can't be written with other bounds
+        let diagram = Value::Map(vec![(
+            Value::Text("classifiers".into()),
+            Value::Array(vec![Value::Map(vec![
+                (Value::Text("kind".into()), Value::Text("class".into())),
+                (Value::Text("name".into()), Value::Text("A".into())),
+                (
+                    Value::Text("attributes".into()),
+                    Value::Array(vec![Value::Map(vec![
+                        (Value::Text("name".into()), Value::Text("x".into())),
+                        (
+                            Value::Text("multiplicity".into()),
+                            Value::Map(vec![
+                                (Value::Text("lower".into()), Value::Integer(1.into())),
+                                (Value::Text("upper".into()), Value::Integer(3.into())),
+                                (Value::Text("shorthand".into()), Value::Bool(true)),
+                            ]),
+                        ),
+                    ])]),
+                ),
+            ])]),
+        )]);
+        let error = unparse(&diagram).unwrap_err().to_string();
+        assert_eq!(error, "the diagram can't be written as source; it would change to\nclass A {\n  x[1..3]\n}");
+    }
+
+    #[test]
+    fn test_unparse_names() {
+        test_unparse(r#"class "a²" as A"#, r#"class "a²" as A"#);
+        test_unparse("class café\nclass \"class\"", "class café\nclass \"class\"");
+        test_unparse("class A {\n  x[*]\n  y[0..*]\n}", "class A {\n  x[*]\n  y[0..*]\n}");
+    }
+}
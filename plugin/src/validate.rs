@@ -0,0 +1,182 @@
+use std::collections::BTreeSet;
+
+use crate::diagnostic::Diagnostic;
+use crate::layout;
+use crate::model::{self, Classifier, Diagram, Edge, MetaSpan, Note, NoteAnchor, Package};
+
+/// The contents of the diagram or of one package, with the package's qualified name.
+struct Scope<'a, 'input> {
+    prefix: String,
+    classifiers: &'a [Classifier<'input>],
+    edges: &'a [Edge<'input>],
+    notes: &'a [Note<'input>],
+}
+
+fn scopes<'a, 'input>(diagram: &'a Diagram<'input>) -> Vec<Scope<'a, 'input>> {
+    fn visit<'a, 'input>(scopes: &mut Vec<Scope<'a, 'input>>, prefix: &str, packages: &'a [Package<'input>]) {
+        for package in packages {
+            let prefix = model::qualify(prefix, package.name);
+            scopes.push(Scope {
+                prefix: prefix.clone(),
+                classifiers: &package.classifiers,
+                edges: &package.edges,
+                notes: &package.notes,
+            });
+            visit(scopes, &prefix, &package.packages);
+        }
+    }
+
+    let mut scopes = vec![Scope {
+        prefix: String::new(),
+        classifiers: &diagram.classifiers,
+        edges: &diagram.edges,
+        notes: &diagram.notes,
+    }];
+    visit(&mut scopes, "", &diagram.packages);
+    scopes
+}
+
+/// Checks that a diagram makes sense: classifiers must have unique (qualified) ids, all
+/// references in edges, notes and metas must resolve to classifiers or members, and relative
+/// positions must not contradict each other. Diagnostics are located in `source`, which the
+/// diagram was parsed from.
+pub fn validate(source: &str, diagram: &Diagram<'_>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // diagnostics about an element point at its first line, and those about its metas at these
+    let element = |span: MetaSpan| (span.start, span.end);
+    let metas = |span: MetaSpan| span.metas.unwrap_or((span.start, span.end));
+
+    let mut ids = BTreeSet::new();
+    for scope in scopes(diagram) {
+        for classifier in scope.classifiers {
+            let id = model::qualify(&scope.prefix, classifier.id.unwrap_or(&classifier.name));
+            if !ids.insert(id.clone()) {
+                let diagnostic = Diagnostic::error(format!("duplicate classifier `{}`", id));
+                diagnostics.push(diagnostic.at(source, element(classifier.span)));
+            }
+        }
+    }
+
+    let mut diagram = diagram.clone();
+    diagram.resolve_references();
+    let ids = diagram.qualified_ids();
+    let scopes = scopes(&diagram);
+
+    let unresolved = |name: &str, range| {
+        let message = || format!("unresolved reference `{}`", name);
+        (!ids.contains_key(name)).then(|| Diagnostic::error(message()).at(source, range))
+    };
+
+    let classifier_metas = scopes.iter().flat_map(|scope| scope.classifiers).map(|classifier| (&classifier.meta, classifier.span));
+    let note_metas = scopes.iter().flat_map(|scope| scope.notes).map(|note| (&note.meta, note.span));
+    let edge_metas = scopes.iter().flat_map(|scope| scope.edges).map(|edge| (&edge.meta, edge.span));
+    for (meta, span) in classifier_metas.chain(note_metas).chain(edge_metas) {
+        for reference in meta.values().flat_map(|meta| meta.references()) {
+            diagnostics.extend(unresolved(reference, metas(span)));
+        }
+    }
+
+    for edge in scopes.iter().flat_map(|scope| scope.edges) {
+        diagnostics.extend(unresolved(&edge.a, element(edge.span)));
+        diagnostics.extend(unresolved(&edge.b, element(edge.span)));
+    }
+
+    let notes = scopes.iter().flat_map(|scope| scope.notes);
+    for (anchor, span) in notes.flat_map(|note| note.anchors.iter().map(|anchor| (anchor, element(note.span)))) {
+        match anchor {
+            NoteAnchor::Classifier { id } => {
+                diagnostics.extend(unresolved(id, span));
+            }
+            NoteAnchor::Member { classifier, member } => match unresolved(classifier, span) {
+                Some(diagnostic) => diagnostics.push(diagnostic),
+                None if !ids[classifier.as_ref()].contains(member.as_ref()) => {
+                    let message = format!("unresolved reference `{}::{}`", classifier, member);
+                    diagnostics.push(Diagnostic::error(message).at(source, span));
+                }
+                None => {}
+            },
+            NoteAnchor::Edge { a, b, a_end, b_end } => {
+                let errors = [unresolved(a, span), unresolved(b, span)].into_iter().flatten().collect::<Vec<_>>();
+                let mut edges = scopes.iter().flat_map(|scope| scope.edges);
+                if errors.is_empty() && !edges.any(|edge| anchor.is_on_edge(edge)) {
+                    let message = if *a_end == model::AssociationEnd::default() && *b_end == model::AssociationEnd::default() {
+                        format!("there is no edge between `{}` and `{}`", a, b)
+                    } else {
+                        format!("there is no association `{}`", anchor)
+                    };
+                    diagnostics.push(Diagnostic::warning(message).at(source, span));
+                }
+                diagnostics.extend(errors);
+            }
+        }
+    }
+
+    diagnostics.extend(layout::check(source, &diagram));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn test_validate(source: &str, expected: &[&str]) {
+        let diagram = parser::parse(source).unwrap();
+        let diagnostics = validate(source, &diagram);
+        let actual = diagnostics.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_validate_classifiers() {
+        test_validate("class A\nclass B", &[]);
+        test_validate(
+            "class A\nclass A\nclass B as A\nclass C as D\nclass D",
+            &["error: duplicate classifier `A`", "error: duplicate classifier `A`", "error: duplicate classifier `D`"],
+        );
+        test_validate("class A\npackage p {\n  class A\n}", &[]);
+        test_validate("package p {\n  class A\n}\npackage p {\n  class A\n}", &["error: duplicate classifier `p::A`"]);
+    }
+
+    #[test]
+    fn test_validate_references() {
+        test_validate("class A\nclass B\nA -- B", &[]);
+        test_validate("class A as X\nA -- X", &[]);
+        test_validate("class A as X\nclass X as Y\nA -- X\nX -- Y", &[]);
+        test_validate("package p {\n  class A as X\n}\nclass B\nB -- p::A\nnote on p::A \"a\"", &[]);
+        test_validate(
+            "class A\npackage p {\n  class B\n  A -- B\n  A -- C\n}\nA -- p::B\nA -- B",
+            &["error: unresolved reference `B`", "error: unresolved reference `C`"],
+        );
+        test_validate(
+            "class A\npackage p {\n  class B\n  #[via(B.east, C.west + (1, 0))]\n  A -- B\n}",
+            &["error: unresolved reference `C`"],
+        );
+    }
+
+    #[test]
+    fn test_validate_relative_positions() {
+        test_validate("class A\n#[right-of(A), align-y(A)]\nclass B\n#[below(B)]\nnote \"a\"", &[]);
+        test_validate("#[right-of(B)]\nclass A\n#[align-x(A)]\nnote \"a\"", &["error: unresolved reference `B`"]);
+        test_validate(
+            "#[below(A)]\nclass A",
+            &["error: `below(A)` of `A` forms a cycle with contradicting relative positions"],
+        );
+    }
+
+    #[test]
+    fn test_validate_notes() {
+        test_validate("class A {\n  x\n}\nnote on A, A::x \"a\"", &[]);
+        test_validate(
+            "class A {\n  x\n}\nnote on B, A::y \"a\"",
+            &["error: unresolved reference `B`", "error: unresolved reference `A::y`"],
+        );
+        test_validate("class A\nclass B\nA -- B\nnote on B -- A \"a\"", &[]);
+        test_validate("class A\nclass B\nnote on A -- B \"a\"", &["warning: there is no edge between `A` and `B`"]);
+        test_validate("class A\nclass B\nA *--> B\nA -- B\nnote on B <--* A, A -- B \"a\"", &[]);
+        test_validate(
+            "class A\nclass B\nA *--> B\nnote on A o--> B \"a\"",
+            &["warning: there is no association `A o--> B`"],
+        );
+    }
+}
@@ -1,59 +1,122 @@
-#let attribute(
-  visibility: none,
+#let typed-element(
   name: none,
   type: none,
+  multiplicity: none,
+  default: none,
+  properties: (),
 ) = {
   assert.ne(name, none, message: "name is required")
 
+  name
+  if type != none [: #type]
+  if multiplicity != none {
+    let (lower, upper, ..) = multiplicity
+    if multiplicity.at("shorthand", default: false) and lower == 0 and upper == none {
+      "[*]"
+    } else if upper == lower {
+      "[" + str(lower) + "]"
+    } else {
+      "[" + str(lower) + ".." + if upper == none { "*" } else { str(upper) } + "]"
+    }
+  }
+  if default != none {
+    " = " + default
+  }
+  if properties.len() > 0 {
+    " {" + properties.join(", ") + "}"
+  }
+}
+
+#let attribute(
+  visibility: none,
+  static: false,
+  derived: false,
+  ..args
+) = {
   (visibility, {
+    show: it => if static { underline(it) } else { it }
+    if derived [/]
+    typed-element(..args)
+  })
+}
+
+#let literal(
+  name: none,
+  arguments: none,
+  value: none,
+) = {
+  assert.ne(name, none, message: "name is required")
+
+  (none, {
     name
-    if type != none [: #type]
+    if arguments != none [(#arguments.join[, ])]
+    if value != none {
+      " = " + value
+    }
   })
 }
 
+#let template-parameter(
+  name: none,
+  bound: none,
+  default: none,
+) = {
+  assert.ne(name, none, message: "name is required")
+
+  name
+  if bound != none [: #bound]
+  if default != none {
+    " = " + default
+  }
+}
+
 #let operation(
   visibility: none,
+  static: false,
+  abstract: false,
+  query: false,
   name: none,
   parameters: (),
   return-type: none,
+  raised-exceptions: (),
+  properties: (),
 ) = {
-  let parameter(
-    name: none,
-    type: none,
-  ) = {
-    assert.ne(name, none, message: "name is required")
-
-    name
-    if type != none [: #type]
+  let parameter(direction: none, ..args) = {
+    if direction != none [#direction ]
+    typed-element(..args)
   }
 
   assert.ne(name, none, message: "name is required")
 
   (visibility, {
+    set text(style: "italic") if abstract
+    show: it => if static { underline(it) } else { it }
     name
     [(#parameters.map(p => parameter(..p)).join[, ])]
     if return-type != none [: #return-type]
+    if raised-exceptions.len() > 0 {
+      " throws " + raised-exceptions.join(", ")
+    }
+    let properties = (if query { ("query",) } else { () }) + properties
+    if properties.len() > 0 {
+      " {" + properties.join(", ") + "}"
+    }
   })
 }
 
-#let classifier(
+// the box of a classifier, without placing it in a diagram; plum() measures these to lay out the
+// diagram
+#let body(
   name,
-  pos: auto,
-  id: auto,
   abstract: auto,
   final: false,
   stereotypes: (),
   kind: "class",
+  template-parameters: (),
+  literals: (),
   attributes: auto,
   operations: (),
-  ..args
 ) = {
-  import "imports.typ": fletcher.node
-
-  assert.ne(pos, auto, message: "automatic positioning is currently not supported. add #[pos(x, y)] to each classifier")
-
-  if id == auto { id = name }
-  if type(id) == str { id = label(id) }
   if abstract == auto { abstract = kind == "interface" }
   if attributes == auto {
     attributes = if kind == "interface" { none } else { () }
@@ -73,6 +136,9 @@
     set text(style: "italic") if abstract
     name
   }
+  let literals = if literals.len() > 0 {
+    literals.map(l => literal(..l)).join()
+  }
   let attributes = if attributes != none {
     if attributes.len() > 0 {
       attributes.map(a => attribute(..a)).join()
@@ -88,21 +154,93 @@
     }
   }
 
-  let body = {
-    set grid.hline(stroke: 0.5pt)
-    show: block.with(stroke: 0.5pt, radius: 2pt)
+  set grid.hline(stroke: 0.5pt)
+  show: block.with(stroke: 0.5pt, radius: 2pt)
+
+  grid(
+    columns: 2,
+    column-gutter: -0.5em,
+    align: (center, start),
+    inset: 0.3em,
 
-    grid(
-      columns: 2,
-      column-gutter: -0.5em,
-      align: (center, start),
-      inset: 0.3em,
+    grid.cell(colspan: 2, title),
+    ..if literals != none {(grid.hline(), ..literals)},
+    ..if attributes != none {(grid.hline(), ..attributes)},
+    ..if operations != none {(grid.hline(), ..operations)},
+  )
 
-      grid.cell(colspan: 2, title),
-      ..if attributes != none {(grid.hline(), ..attributes)},
-      ..if operations != none {(grid.hline(), ..operations)},
-    )
+  // the template parameters are shown in a dashed box overlapping the top right corner
+  if template-parameters.len() > 0 {
+    place(top + right, dx: 0.8em, dy: -0.8em, box(
+      fill: white,
+      stroke: (thickness: 0.5pt, dash: "dashed"),
+      inset: 0.2em,
+      text(0.8em, template-parameters.map(p => template-parameter(..p)).join[, ]),
+    ))
   }
+}
+
+#let classifier(
+  name,
+  pos: auto,
+  id: auto,
+  abstract: auto,
+  final: false,
+  stereotypes: (),
+  kind: "class",
+  template-parameters: (),
+  literals: (),
+  attributes: auto,
+  operations: (),
+  ..args
+) = {
+  import "imports.typ": fletcher.node
+
+  assert.ne(pos, auto, message: "a position is required; plum() computes missing positions automatically")
+
+  if id == auto { id = name }
+  if type(id) == str { id = label(id) }
 
+  let body = body(
+    name,
+    abstract: abstract,
+    final: final,
+    stereotypes: stereotypes,
+    kind: kind,
+    template-parameters: template-parameters,
+    literals: literals,
+    attributes: attributes,
+    operations: operations,
+  )
   node(pos, body, name: id, shape: "rect", ..args)
 }
+
+#let package(
+  name,
+  members,
+  id: auto,
+  ..args
+) = {
+  import "imports.typ": fletcher.node
+
+  if id == auto { id = name }
+  if type(id) == str { id = label(id) }
+
+  // an empty package has no extent to enclose
+  if members.len() == 0 { return }
+
+  let tab = {
+    set text(0.8em, weight: "bold")
+    box(stroke: 0.5pt, inset: 0.3em, name)
+  }
+
+  node(
+    enclose: members,
+    align(top + left, move(dy: -1.6em, tab)),
+    name: id,
+    shape: "rect",
+    stroke: 0.5pt,
+    inset: 1em,
+    ..args,
+  )
+}
@@ -102,6 +102,11 @@
   kind,
   via: (),
   bend: none,
+  loop: none,
+  from: none,
+  to: none,
+  // only relevant to the plugin's edge routing
+  route: none,
   ..args
 ) = {
   import "imports.typ": fletcher.edge
@@ -111,7 +116,7 @@
 
   let opts = (dash: none, marks: ())
 
-  if kind.type in ("realization", "dependency") {
+  if kind.type in ("realization", "dependency", "binding") {
     opts.dash = "densely-dashed"
   }
   let marks = if kind.type in ("realization", "generalization") {
@@ -119,7 +124,7 @@
       opts.marks.push(none)
     }
     opts.marks.push("plum-|>")
-  } else if kind.type in ("dependency",) {
+  } else if kind.type in ("dependency", "binding") {
     if kind.direction == "a-to-b" {
       opts.marks.push(none)
     }
@@ -152,11 +157,64 @@
     opts.marks += marks(b, "b")
   }
 
+  // fletcher supports only one label per edge; additional labels are drawn on invisible copies
+  let labels = ()
+  if kind.type in ("dependency",) {
+    let stereotype = kind.at("stereotype", default: none)
+    let name = kind.at("name", default: none)
+    let label = (
+      if stereotype != none [«#stereotype»],
+      name,
+    ).filter(x => x != none)
+    if label.len() > 0 {
+      labels.push((label: label.join(linebreak()), label-pos: 0.5, label-side: left))
+    }
+  } else if kind.type in ("binding",) {
+    let substitutions = kind.substitutions.map(((formal, actual)) => [#formal → #actual])
+    labels.push((label: [«bind» \<#substitutions.join[, ]\>], label-pos: 0.5, label-side: left))
+  } else if kind.type in ("association",) {
+    let name = kind.at("name", default: none)
+    let reading-direction = kind.at("reading-direction", default: none)
+    if name != none {
+      if reading-direction == "a-to-b" {
+        name = [#name ▸]
+      } else if reading-direction == "b-to-a" {
+        name = [◂ #name]
+      }
+      labels.push((label: name, label-pos: 0.5, label-side: left))
+    }
+    for (end, pos) in ((kind.a, 0.1), (kind.b, 0.9)) {
+      let role = end.at("role", default: none)
+      let multiplicity = end.at("multiplicity", default: none)
+      if role != none {
+        labels.push((label: role, label-pos: pos, label-side: left))
+      }
+      if multiplicity != none {
+        labels.push((label: multiplicity, label-pos: pos, label-side: right))
+      }
+    }
+  }
+
   if bend != none {
     opts.bend = bend * 1rad
   }
+  if loop != none {
+    opts.loop-angle = loop * 1rad
+  }
+  // the plugin resolves `via` points relative to classifiers, and the sides edges leave and enter
+  // classifiers to points outside these sides. What it couldn't resolve is left as is, and ignored
+  via = via.filter(point => type(point) == array)
+  if type(from) == array {
+    via.insert(0, from)
+  }
+  if type(to) == array {
+    via.push(to)
+  }
 
   edge(a, ..via, b, ..opts, ..args)
+  for label in labels {
+    edge(a, ..via, b, ..opts, ..label, stroke: none, marks: ())
+  }
 }
 
   // uml-edge(<subj>, <conc-subj>, "generalize-")
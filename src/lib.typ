@@ -1,8 +1,36 @@
 #import "classifier.typ"
 #import "edge.typ" as edge: MARKS, add-marks
+#import "note.typ"
 
 #let _p = plugin("parser.wasm")
 
+// the text style of rendered diagrams, and the gap between fletcher's grid cells
+#let _text-style = (font: ("FreeSans",), size: 0.8em)
+#let _spacing = 3em
+
+// the sizes of the rendered classifiers of a laid out diagram by qualified id, and the spacing,
+// both in points, for the second phase of the layout. requires context
+#let _measure(diagram) = {
+  let sizes(scope, prefix) = {
+    let qualify(id) = if prefix == none { id } else { prefix + "::" + id }
+
+    let result = (:)
+    for (name, ..args) in scope.at("classifiers", default: ()) {
+      let id = qualify(args.remove("id", default: name))
+      let _ = args.remove("pos", default: none)
+      let size = measure(text(.._text-style, classifier.body(name, ..args)))
+      result.insert(id, (size.width.pt(), size.height.pt()))
+    }
+    for package in scope.at("packages", default: ()) {
+      result += sizes(package, qualify(package.name))
+    }
+    result
+  }
+  // the spacing is given in the diagram's text size
+  let spacing = _spacing.em * _text-style.size.em * text.size
+  (sizes: sizes(diagram, none), spacing: spacing.pt())
+}
+
 /// Parses a diagram via a WASM plugin.
 ///
 /// #example(mode: "markup", dir: ttb, ```typ
@@ -24,7 +52,135 @@
   cbor.decode(_p.parse(cbor.encode(diagram)))
 }
 
-/// Parses and processes a diagram.
+/// Turns a diagram dictionary, as returned by `parse()` or built from data, back into source.
+/// Parsing the result gives the same dictionary again. Fields that are empty or `false` can be
+/// left out. Fails if the dictionary can't be written as valid source, e.g. because an attribute
+/// name contains a space, or only as source that parses to a different dictionary, e.g. because a
+/// multiplicity is marked as the shorthand `[*]` but has other bounds than `0..*`.
+///
+/// #example(mode: "markup", dir: ttb, ```typ
+/// #raw(plum.unparse((
+///   classifiers: (
+///     (kind: "class", name: "Order", attributes: ((visibility: "+", name: "id"),)),
+///     (kind: "interface", name: "Entity", pos: (1, 0)),
+///   ),
+///   edges: ((a: "Order", b: "Entity", kind: (type: "realization", direction: "a-to-b")),),
+/// )))
+/// ```)
+///
+/// - diagram (dict): the diagram to turn into source
+/// -> str
+#let unparse(diagram) = {
+  cbor.decode(_p.unparse(cbor.encode(diagram)))
+}
+
+/// Checks a diagram for syntax errors, duplicate classifiers and unresolved references via a
+/// WASM plugin. Each diagnostic is a dictionary with a `severity` (`"error"` or `"warning"`), a
+/// `message` and a `span` (with `start` and `end` locations, each having a byte `offset` as well as
+/// one-based `line` and `column`) locating the offending element. Syntax errors may additionally
+/// have a list of `expected` tokens and a `hint`. Classifiers can be referred to by their name as
+/// well as by their `as` id.
+///
+/// #example(mode: "markup", dir: ttb, ````typ
+/// #plum.validate(```
+/// class A
+/// class A
+/// A -- B
+/// ```)
+/// ````)
+/// #example(mode: "markup", dir: ttb, ````typ
+/// #plum.validate(```
+/// class A class B
+/// ```)
+/// ````)
+///
+/// - diagram (str): the expression to check
+/// -> array
+#let validate(diagram) = {
+  if type(diagram) == content and diagram.func() == raw {
+    diagram = diagram.text
+  }
+  cbor.decode(_p.validate(cbor.encode(diagram)))
+}
+
+/// Lays out a diagram and returns its source with the computed positions and edge routes written
+/// into it as `#[pos(...)]` and `#[via(...)]`, so that later edits don't move the existing
+/// elements around. Comments and formatting are kept. Like `plum()`, this measures the rendered
+/// classifiers, so it has to be called in a context, where the text size is the one the diagram
+/// will be drawn at.
+///
+/// #example(mode: "markup", dir: ttb, ````typ
+/// #context raw(plum.freeze(```
+/// class A
+/// // subtypes
+/// class B
+/// B --|> A
+/// ```))
+/// ````)
+///
+/// - diagram (str): the diagram to lay out
+/// - layout (str): how classifiers without a position are placed; see `plum()`
+/// - routing (str): how edges without a `#[route]` are drawn; see `plum()`
+/// -> str
+#let freeze(diagram, layout: "layered", routing: "auto") = {
+  if type(diagram) == content and diagram.func() == raw {
+    diagram = diagram.text
+  }
+  let source = cbor.encode(diagram)
+  let options = cbor.encode((algorithm: layout, routing: routing))
+  let measurements = _measure(cbor.decode(_p.layout(source, options)))
+  cbor.decode(_p.freeze(source, options, cbor.encode(measurements)))
+}
+
+/// Formats the source of a diagram: lines are indented according to their nesting in packages
+/// and classifiers, whitespace is normalized, and blank lines are merged. Comments are kept, and
+/// formatting the result again doesn't change it. The same formatter is available on the command
+/// line as `plum fmt`.
+///
+/// #example(mode: "markup", dir: ttb, ````typ
+/// #raw(plum.format(align-colons: true, ```
+/// class Order {
+/// - id: Int
+///     + total:   Money
+/// }
+/// #[bend(30deg),pos(0, 0)]
+/// Order -- Order
+/// ```))
+/// ````)
+///
+/// - diagram (str): the diagram to format
+/// - indent (int): the number of spaces per level of nesting
+/// - align-colons (bool): whether the `:` of the attributes of a classifier are put in the same
+///   column
+/// - sort-members (bool): whether attributes and operations are sorted by visibility, public
+///   ones first
+/// - normalize-metas (bool): whether the metas in a `#[...]` block are put in a fixed order, with
+///   `pos` first
+/// -> str
+#let format(diagram, indent: 2, align-colons: false, sort-members: false, normalize-metas: true) = {
+  if type(diagram) == content and diagram.func() == raw {
+    diagram = diagram.text
+  }
+  let options = (
+    indent: indent,
+    align-colons: align-colons,
+    sort-members: sort-members,
+    normalize-metas: normalize-metas,
+  )
+  cbor.decode(_p.format(cbor.encode(diagram), cbor.encode(options)))
+}
+
+/// Parses and processes a diagram. Classifiers and notes without a `#[pos(x, y)]` are placed
+/// automatically according to the `layout` parameter; positions that are given are kept. Positions
+/// can also be given relative to other classifiers, e.g. `#[right-of(A)]`, `#[below(A, 2)]` or
+/// `#[align-x(A, B)]`. Edges that would cross other nodes are routed around them;
+/// `#[route(orthogonal)]` or `#[route(straight)]` on an edge overrides the `routing` parameter.
+/// `via` points can be given relative to classifiers, e.g. `#[via(Order.east + (0.5, 0))]`, and
+/// `#[from(south), to(north)]` selects the sides of the classifiers an edge attaches to.
+/// Self-associations are drawn as loops, and several edges between the same classifiers are bent
+/// apart. The layout happens in two phases: the classifiers are rendered and measured first, and
+/// their sizes are then used for placing them, so that large classifiers get more room, and for
+/// routing the edges.
 ///
 /// #example(mode: "markup", dir: ttb, ````typ
 /// #plum.plum(```plum
@@ -34,26 +190,108 @@
 ///   exception Baz
 /// ```)
 /// ````)
+/// #example(mode: "markup", dir: ttb, ````typ
+/// #plum.plum(```plum
+///   abstract class Shape
+///   class Circle
+///   class Square
+///   Circle --|> Shape
+///   Square --|> Shape
+/// ```)
+/// ````)
+/// #example(mode: "markup", dir: ttb, ````typ
+/// #plum.plum(layout: "force", ```plum
+///   class Customer
+///   class Order
+///   class Product
+///   Customer -- Order
+///   Order -- Product
+/// ```)
+/// ````)
 ///
 /// - diagram (str): the expression to parse
+/// - layout (str): how classifiers without a position are placed: `"layered"` puts supertypes
+///   above their subtypes, which suits inheritance hierarchies; `"force"` puts related classifiers
+///   close to each other, which suits diagrams that mostly consist of associations
+/// - routing (str): how edges without a `#[route]` are drawn: `"auto"` routes edges around nodes
+///   they would otherwise cross, `"orthogonal"` draws all edges with horizontal and vertical
+///   segments, `"straight"` only uses `#[via]` and `#[bend]`
 /// -> dict
-#let plum(diagram) = {
+#let plum(diagram, layout: "layered", routing: "auto") = {
   import "imports.typ": fletcher
 
-  set text(font: ("FreeSans",), size: 0.8em)
+  if type(diagram) == content and diagram.func() == raw {
+    diagram = diagram.text
+  }
+  // measuring the classifiers requires the surrounding text size
+  context {
+    let source = diagram
+    let options = cbor.encode((algorithm: layout, routing: routing))
+    // parse and assign positions to classifiers and notes that don't have one
+    let diagram = cbor.decode(_p.layout(cbor.encode(source), options))
+    // lay the diagram out again, this time making room for and routing edges around the measured
+    // boxes
+    let measurements = _measure(diagram)
+    let diagram = cbor.decode(_p.arrange(cbor.encode(source), options, cbor.encode(measurements)))
 
-  let diagram = parse(diagram)
+    // the positions of all classifiers by qualified id, used for anchoring notes to edges
+    let positions(scope, prefix) = {
+      let qualify(id) = if prefix == none { id } else { prefix + "::" + id }
 
-  fletcher.diagram(
-    node-inset: 0pt,
-    axes: (ltr, ttb),
-    {
-      for (name, ..args) in diagram.classifiers {
-        classifier.classifier(name, ..args)
+      let result = (:)
+      for (name, ..args) in scope.at("classifiers", default: ()) {
+        if "pos" in args {
+          result.insert(qualify(args.at("id", default: name)), args.pos)
+        }
       }
-      for (a, b, kind, ..args) in diagram.edges {
-        edge.edge(a, b, kind, ..args)
+      for package in scope.at("packages", default: ()) {
+        result += positions(package, qualify(package.name))
       }
+      result
     }
-  )
+    let positions = positions(diagram, none)
+
+    // draws the classifiers, edges and packages of a diagram or package. returns the ids of all
+    // nodes drawn at this level, so that an enclosing package can be drawn around them
+    let items(scope, prefix) = {
+      let qualify(id) = if prefix == none { id } else { prefix + "::" + id }
+
+      let ids = ()
+      let body = {
+        for (name, ..args) in scope.at("classifiers", default: ()) {
+          let id = qualify(args.remove("id", default: name))
+          ids.push(label(id))
+          classifier.classifier(name, id: id, ..args)
+        }
+        for (a, b, kind, ..args) in scope.at("edges", default: ()) {
+          edge.edge(a, b, kind, ..args)
+        }
+        for (i, (text, ..args)) in scope.at("notes", default: ()).enumerate() {
+          let id = qualify("note-" + str(i))
+          if "pos" in args {
+            ids.push(label(id))
+          }
+          note.note(text, id: id, positions: positions, ..args)
+        }
+        for package in scope.at("packages", default: ()) {
+          let id = qualify(package.name)
+          let (members, body) = items(package, id)
+          if members.len() > 0 {
+            ids.push(label(id))
+          }
+          body
+          classifier.package(package.name, members, id: id)
+        }
+      }
+      (ids, body)
+    }
+
+    set text(.._text-style)
+    fletcher.diagram(
+      spacing: _spacing,
+      node-inset: 0pt,
+      axes: (ltr, ttb),
+      items(diagram, none).at(1),
+    )
+  }
 }
@@ -0,0 +1,42 @@
+// a rectangle with a folded top right corner
+#let shape(node, extrude) = {
+  import "@preview/cetz:0.3.1": draw
+
+  let (w, h) = node.size
+  let (x, y) = (w/2 + extrude, h/2 + extrude)
+  let d = calc.min(6pt, w/2, h/2)
+
+  draw.line((-x, -y), (-x, y), (x - d, y), (x, y - d), (x, -y), close: true)
+  draw.line((x - d, y), (x - d, y - d), (x, y - d))
+}
+
+#let note(
+  text,
+  pos: auto,
+  id: auto,
+  anchors: (),
+  positions: (:),
+  ..args
+) = {
+  import "imports.typ": fletcher.node, fletcher.edge
+
+  assert.ne(pos, auto, message: "a position is required; plum() computes missing positions automatically")
+
+  if type(id) == str { id = label(id) }
+
+  node(pos, align(start, text), name: id, shape: shape, stroke: 0.5pt, inset: 0.5em, ..args)
+
+  for anchor in anchors {
+    let target = if anchor.type == "classifier" {
+      label(anchor.id)
+    } else if anchor.type == "member" {
+      label(anchor.classifier)
+    } else if anchor.type == "edge" {
+      // attach to the midpoint between the edge's endpoints
+      let (ax, ay) = positions.at(anchor.a)
+      let (bx, by) = positions.at(anchor.b)
+      ((ax + bx)/2, (ay + by)/2)
+    }
+    edge(id, target, dash: "dashed", stroke: 0.5pt)
+  }
+}
@@ -3,5 +3,18 @@
 // the output is not relevant for this test
 #set page(width: 0pt, height: 0pt)
 
-// TODO real tests
-#assert(true)
+#let source = "class A\nclass B\nB --|> A\nnote on A \"a\""
+
+#assert.eq(plum.parse(source).classifiers.map(classifier => classifier.name), ("A", "B"))
+#plum.plum(source)
+
+#assert.eq(plum.validate("class A\nA -- B").map(diagnostic => diagnostic.message), ("unresolved reference `B`",))
+
+#context assert.eq(
+  plum.freeze(source),
+  "#[pos(0, 0)]\nclass A\n#[pos(0, 1)]\nclass B\nB --|> A\n#[pos(1, 0)]\nnote on A \"a\"",
+)
+
+#assert.eq(plum.format("class A {\n+ x\n}"), "class A {\n  + x\n}")
+
+#assert.eq(plum.unparse(plum.parse(source)), source)